@@ -6,10 +6,13 @@ use criterion::{Fun, Criterion};
 
 use synth::oscillator::{saw, sine, triangle, square};
 use synth::foundation::module::{SamplingParameters, SoundModule};
-use synth::foundation::generator::{SignalIterator, Const, constant};
-use synth::foundation::types::Frequency;
+use synth::foundation::generator::{SignalGenerator, SignalIterator, Const, constant};
+use synth::foundation::filter::Filter;
+use synth::foundation::types::{Frequency, units};
+use synth::filters::{Delay, Echo, FilteredExt, LowPassRC};
+use synth::noise::white_noise;
 use synth::oscillator::Oscillator;
-use synth::waveform::{Waveform, Saw, Sine, Rect, Triangle};
+use synth::waveform::{Waveform, Saw, Sine, Rect, Triangle, BlSaw, BlPulse, BlTriangle};
 
 // fn fract_bench(x: f32) -> f32 {
 //     x.fract()
@@ -54,7 +57,54 @@ fn oscillator_bench(c: &mut Criterion) {
     c.bench_function("sine", move |b| b.iter(|| SignalIterator(make_test_osc(Sine)).take(num_samples).count()));
     c.bench_function("square", move |b| b.iter(|| SignalIterator(make_test_osc(Rect(0.5))).take(num_samples).count()));
     c.bench_function("triangle", move |b| b.iter(|| SignalIterator(make_test_osc(Triangle)).take(num_samples).count()));
+    c.bench_function("bl_saw", move |b| b.iter(|| SignalIterator(make_test_osc(BlSaw)).take(num_samples).count()));
+    c.bench_function("bl_square", move |b| b.iter(|| SignalIterator(make_test_osc(BlPulse(0.5))).take(num_samples).count()));
+    c.bench_function("bl_triangle", move |b| b.iter(|| SignalIterator(make_test_osc(BlTriangle)).take(num_samples).count()));
+}
+
+/// Compare generating samples one at a time with generating them in blocks.
+fn bench_block<S, F>(c: &mut Criterion, name: &str, make: F) where
+    S: SignalGenerator<Output=f32>,
+    F: Fn() -> S + Copy + 'static
+{
+    let num_samples = 44100;
+    let per_sample = Fun::new("per_sample", move |b, _| {
+        let mut generator = make();
+        let mut output = vec![0.0; num_samples];
+        b.iter(|| for sample in output.iter_mut() { *sample = generator.next() })
+    });
+    let block = Fun::new("block", move |b, _| {
+        let mut generator = make();
+        let mut output = vec![0.0; num_samples];
+        b.iter(|| for chunk in output.chunks_mut(256) { generator.fill(chunk) })
+    });
+    c.bench_functions(name, vec![per_sample, block], ());
+}
+
+fn block_bench(c: &mut Criterion) {
+    bench_block(c, "block_oscillator", || {
+        let mut osc = saw(sine(constant(2. * units::HZ)).map(|x| (x + 2.) * 220. * units::HZ));
+        osc.set_sampling_parameters(&SamplingParameters::audio_cd());
+        osc
+    });
+    bench_block(c, "block_noise", || {
+        let mut noise = white_noise();
+        noise.set_sampling_parameters(&SamplingParameters::audio_cd());
+        noise
+    });
+    bench_block(c, "block_lowpass", || {
+        let cutoff = sine(constant(0.5 * units::HZ)).map(|x| (x + 1.5) * 1000. * units::HZ);
+        let mut filtered = white_noise().filtered(LowPassRC::new(cutoff));
+        filtered.set_sampling_parameters(&SamplingParameters::audio_cd());
+        filtered
+    });
+    bench_block(c, "block_delays", || {
+        let mut filtered = white_noise().filtered(Delay::new(0.01 * units::S).chain(Echo::new(0.25 * units::S, 0.5)));
+        filtered.set_sampling_parameters(&SamplingParameters::audio_cd());
+        filtered
+    });
 }
 
 criterion_group!(oscillators, oscillator_bench);
-criterion_main!(oscillators);
+criterion_group!(blocks, block_bench);
+criterion_main!(oscillators, blocks);
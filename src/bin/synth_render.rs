@@ -0,0 +1,405 @@
+//! Renders a patch file to a WAV file or to raw samples, optionally playing
+//! the notes of a MIDI file on it.
+
+extern crate synth;
+
+use std::io::Write;
+use std::str::FromStr;
+
+use synth::filters::dynamics::{db_to_gain, gain_to_db};
+use synth::foundation::{BoxedGenerator, Duration, Frequency, SignalGenerator, SoundModule, SamplingParameters};
+use synth::io::wav::{SampleFormat, WavSpec, WavWriter};
+use synth::midi::smf::{MidiFile, MidiPlayer};
+use synth::patch::Patch;
+use synth::patch::format;
+use synth::tuning::Tuning;
+use synth::voice::Polyphony;
+
+const USAGE: &str = "\
+usage: synth-render [options] <patch> -o <output>
+
+options:
+  -o, --output <path>          where to write the audio, `-` for stdout
+  -m, --midi <file>            play the notes of a MIDI file on the patch, whose
+                               `voice` module then provides the notes
+      --voices <n>             number of notes playing at once (default: 8)
+      --scale <file>           tune the notes with a Scala scale (.scl)
+      --keyboard-map <file>    map the notes to the degrees of the scale (.kbm)
+  -r, --rate <hertz>           sample rate (default: 44100)
+  -b, --bits <8|16|24|32>      bits per integer sample (default: 16)
+  -f, --float                  write 32 bit floating point samples instead
+  -c, --channels <n>           number of channels the patch is copied to (default: 1)
+  -d, --duration <seconds>     length of the rendering (default: the length of the
+                               MIDI file, or 10 seconds without one)
+  -s, --until-silent           render until the MIDI file has ended and the output
+                               has become silent, but at most for --duration
+                               (default: 600 seconds)
+  -n, --normalize <dbfs>       scale the output so that its peak is at the level
+      --raw                    write raw interleaved samples without a WAV header
+  -h, --help                   show this message";
+
+/// Level below which the output counts as silent.
+const SILENCE_DB: f32 = -80.0;
+/// How long the output has to stay silent to stop rendering.
+const SILENCE_HOLD: f32 = 0.5;
+/// The number of samples generated at once.
+const BLOCK_SIZE: usize = 1024;
+
+#[derive(Debug)]
+struct Options {
+    patch: String,
+    output: String,
+    midi: Option<String>,
+    voices: usize,
+    scale: Option<String>,
+    keyboard_map: Option<String>,
+    sample_rate: f32,
+    format: SampleFormat,
+    channels: u16,
+    duration: Option<f32>,
+    until_silent: bool,
+    normalize: Option<f32>,
+    raw: bool,
+}
+
+fn main() {
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        },
+        Err(message) => {
+            eprintln!("synth-render: {}\n\n{}", message, USAGE);
+            std::process::exit(2);
+        },
+    };
+    if let Err(message) = run(&options) {
+        eprintln!("synth-render: {}", message);
+        std::process::exit(1);
+    }
+}
+
+/// Parse the command line arguments, returning `None` if help was requested.
+fn parse_args<I: Iterator<Item=String>>(mut args: I) -> Result<Option<Options>, String> {
+    let mut patch = None;
+    let mut output = None;
+    let mut options = Options {
+        patch: String::new(),
+        output: String::new(),
+        midi: None,
+        voices: 8,
+        scale: None,
+        keyboard_map: None,
+        sample_rate: 44100.0,
+        format: SampleFormat::Int16,
+        channels: 1,
+        duration: None,
+        until_silent: false,
+        normalize: None,
+        raw: false,
+    };
+    let mut voices = None;
+    let mut bits: Option<u16> = None;
+    let mut float = false;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for `{}`", arg));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-o" | "--output" => output = Some(value()?),
+            "-m" | "--midi" => options.midi = Some(value()?),
+            "--voices" => voices = Some(number(&arg, &value()?).and_then(|voices| positive(&arg, voices))?),
+            "--scale" => options.scale = Some(value()?),
+            "--keyboard-map" => options.keyboard_map = Some(value()?),
+            "-r" | "--rate" => options.sample_rate = number(&arg, &value()?).and_then(|rate| positive(&arg, rate))?,
+            "-b" | "--bits" => bits = Some(number(&arg, &value()?)?),
+            "-f" | "--float" => float = true,
+            "-c" | "--channels" => options.channels = number(&arg, &value()?).and_then(|channels| positive(&arg, channels))?,
+            "-d" | "--duration" => options.duration = Some(number(&arg, &value()?).and_then(|duration| positive(&arg, duration))?),
+            "-s" | "--until-silent" => options.until_silent = true,
+            "-n" | "--normalize" => options.normalize = Some(number(&arg, &value()?)?),
+            "--raw" => options.raw = true,
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option `{}`", arg)),
+            _ if patch.is_none() => patch = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+
+    options.patch = patch.ok_or("missing patch file")?;
+    options.output = output.ok_or("missing output, use `-o -` for stdout")?;
+    options.format = match (bits, float) {
+        (None, true) => SampleFormat::Float32,
+        (Some(_), true) => return Err("`--bits` and `--float` exclude each other".to_string()),
+        (None, false) | (Some(16), false) => SampleFormat::Int16,
+        (Some(8), false) => SampleFormat::Int8,
+        (Some(24), false) => SampleFormat::Int24,
+        (Some(32), false) => SampleFormat::Int32,
+        (Some(bits), false) => return Err(format!("unsupported number of bits {}, expected 8, 16, 24 or 32", bits)),
+    };
+    // the frame size and the byte rate must fit into the WAV header
+    let frame_size = options.channels as u32 * options.format.bytes_per_sample() as u32;
+    if frame_size > u16::MAX as u32 {
+        return Err(format!("too many channels for {} byte samples, at most {}",
+                           options.format.bytes_per_sample(), u16::MAX as u32 / options.format.bytes_per_sample() as u32));
+    }
+    let max_rate = u32::MAX / frame_size;
+    if !(1.0..=max_rate as f64).contains(&(options.sample_rate.round() as f64)) {
+        return Err(format!("`--rate` must be between 1 and {} hertz", max_rate));
+    }
+    if options.keyboard_map.is_some() && options.scale.is_none() {
+        return Err("`--keyboard-map` needs a `--scale`".to_string());
+    }
+    if options.midi.is_none() && (options.scale.is_some() || voices.is_some()) {
+        return Err("`--scale` and `--voices` only apply to MIDI files".to_string());
+    }
+    options.voices = voices.unwrap_or(options.voices);
+    Ok(Some(options))
+}
+
+fn number<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value `{}` for `{}`", value, option))
+}
+
+fn positive<T: PartialOrd + Default>(option: &str, value: T) -> Result<T, String> {
+    if value > T::default() { Ok(value) } else { Err(format!("`{}` must be positive", option)) }
+}
+
+/// Load the patch, played by the MIDI file if there is one. Returns the
+/// generator and the length of the MIDI file.
+fn load(options: &Options) -> Result<(BoxedGenerator<f32>, Option<Duration>), String> {
+    let text = std::fs::read_to_string(&options.patch).map_err(|err| format!("{}: {}", options.patch, err))?;
+    let midi = match options.midi {
+        Some(ref path) => MidiFile::open(path).map_err(|err| format!("{}: {}", path, err))?,
+        None => {
+            let patch = format::load(&text).map_err(|err| format!("{}: {}", options.patch, err))?.patch;
+            return Ok((patch.boxed(), None));
+        },
+    };
+
+    let mut error = None;
+    let voices = Polyphony::new(options.voices, |controls| {
+        format::load_voice(&text, controls).map(|loaded| loaded.patch).unwrap_or_else(|err| {
+            error = Some(err);
+            Patch::new()
+        })
+    });
+    if let Some(err) = error {
+        return Err(format!("{}: {}", options.patch, err));
+    }
+    let voices = match options.scale {
+        Some(ref scale) => {
+            let tuning = Tuning::open(scale, options.keyboard_map.as_ref())
+                .map_err(|err| format!("invalid tuning: {}", err))?;
+            voices.with_tuning(tuning)
+        },
+        None => voices,
+    };
+    let player = MidiPlayer::new(&midi).with_sink(None, voices.keyboard());
+    let length = player.duration();
+    Ok((player.play(voices).boxed(), Some(length)))
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    let (mut generator, midi_length) = load(options)?;
+    let params = SamplingParameters::with_rate(Frequency::from_hertz(options.sample_rate));
+    generator.set_sampling_parameters(&params);
+
+    let seconds = |duration: f32| (params.sample_rate() * Duration::from_seconds(duration)).round() as usize;
+    let max_length = match (options.duration, options.until_silent, midi_length) {
+        (Some(duration), _, _) => seconds(duration),
+        (None, true, _) => seconds(600.0),
+        (None, false, Some(length)) => (params.sample_rate() * length).round() as usize,
+        (None, false, None) => seconds(10.0),
+    };
+    let min_length = midi_length.map_or(0, |length| (params.sample_rate() * length).round() as usize);
+    let silence_hold = if options.until_silent { Some(seconds(SILENCE_HOLD)) } else { None };
+    let mut samples = render(&mut generator, max_length, min_length, silence_hold);
+
+    if let Some(level) = options.normalize {
+        let peak = samples.iter().fold(0.0, |peak: f32, sample| peak.max(sample.abs()));
+        if peak > 0.0 {
+            let gain = db_to_gain(level) / peak;
+            for sample in samples.iter_mut() {
+                *sample *= gain;
+            }
+            eprintln!("normalized by {:+.2} dB", gain_to_db(gain));
+        }
+    }
+
+    write(options, &samples).map_err(|err| format!("{}: {}", options.output, err))?;
+    report(options, &params, &samples);
+    Ok(())
+}
+
+/// Generate at most `max_length` samples. With a `silence_hold`, stop as
+/// soon as the output has been silent for that many samples after
+/// `min_length` and drop the silence at the end.
+fn render<G>(generator: &mut G, max_length: usize, min_length: usize, silence_hold: Option<usize>) -> Vec<f32> where
+    G: SignalGenerator<Output=f32>
+{
+    let silence = db_to_gain(SILENCE_DB);
+    let mut samples = Vec::new();
+    let mut quiet = 0;
+    while samples.len() < max_length {
+        let start = samples.len();
+        samples.resize(max_length.min(start + BLOCK_SIZE), 0.0);
+        generator.fill(&mut samples[start..]);
+        if let Some(hold) = silence_hold {
+            for (index, sample) in samples.iter().enumerate().skip(start) {
+                quiet = if sample.abs() < silence { quiet + 1 } else { 0 };
+                if index + 1 >= min_length && quiet >= hold {
+                    // drop the silence at the end
+                    samples.truncate(index + 1 - quiet);
+                    return samples;
+                }
+            }
+        }
+    }
+    samples
+}
+
+/// Write the samples, copied to each channel.
+fn write(options: &Options, samples: &[f32]) -> std::io::Result<()> {
+    let sink: Box<dyn Write> = if options.output == "-" {
+        Box::new(std::io::stdout())
+    } else {
+        Box::new(std::fs::File::create(&options.output)?)
+    };
+    let mut sink = std::io::BufWriter::new(sink);
+    let channels = options.channels as usize;
+    if options.raw {
+        for &sample in samples {
+            for _ in 0..channels {
+                options.format.write_sample(&mut sink, sample)?;
+            }
+        }
+        return sink.flush();
+    }
+
+    let spec = WavSpec {
+        channels: options.channels,
+        sample_rate: options.sample_rate.round() as u32,
+        format: options.format,
+    };
+    if samples.len() > u32::MAX as usize {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "too long for a WAV file"));
+    }
+    let mut writer = WavWriter::with_length(sink, spec, samples.len() as u32)?;
+    let mut frames = Vec::with_capacity(BLOCK_SIZE * channels);
+    for block in samples.chunks(BLOCK_SIZE) {
+        frames.clear();
+        frames.extend(block.iter().flat_map(|&sample| std::iter::repeat_n(sample, channels)));
+        writer.write_samples(&frames)?;
+    }
+    writer.finish()?.flush()
+}
+
+/// Print the length, peak and RMS level of the rendering.
+fn report(options: &Options, params: &SamplingParameters, samples: &[f32]) {
+    let peak = samples.iter().fold(0.0, |peak: f32, sample| peak.max(sample.abs()));
+    let square_sum = samples.iter().map(|&sample| sample as f64 * sample as f64).sum::<f64>();
+    let rms = (square_sum / samples.len().max(1) as f64).sqrt() as f32;
+    eprintln!("length: {:.3} s", samples.len() as f32 / params.sample_rate().to_hertz());
+    eprintln!("peak:   {:.2} dBFS ({:.4})", gain_to_db(peak), peak);
+    eprintln!("rms:    {:.2} dBFS ({:.4})", gain_to_db(rms), rms);
+    if options.format != SampleFormat::Float32 {
+        let clipped = samples.iter().filter(|sample| sample.abs() > 1.0).count();
+        if clipped > 0 {
+            eprintln!("clipped {} samples, consider `--normalize`", clipped);
+        }
+    }
+}
+
+#[test]
+fn test_parse_args() {
+    let parse = |args: &str| parse_args(args.split_whitespace().map(String::from));
+
+    let options = parse("patch.txt -o out.wav").unwrap().unwrap();
+    assert_eq!(options.patch, "patch.txt");
+    assert_eq!(options.output, "out.wav");
+    assert_eq!(options.voices, 8);
+    assert!(options.format == SampleFormat::Int16);
+    assert!(parse("patch.txt -o - --help").unwrap().is_none());
+
+    let options = parse("patch.txt -o - -m song.mid --voices 8 -b 24 -c 2 -s").unwrap().unwrap();
+    assert_eq!(options.midi.as_deref(), Some("song.mid"));
+    assert_eq!(options.voices, 8);
+    assert!(options.format == SampleFormat::Int24);
+    assert_eq!(options.channels, 2);
+    assert!(options.until_silent);
+    assert!(parse("patch.txt -o - --float").unwrap().unwrap().format == SampleFormat::Float32);
+    assert!(parse("patch.txt -o - -b 16").unwrap().unwrap().format == SampleFormat::Int16);
+
+    assert!(parse("patch.txt -o - -b 16 -f").is_err());
+    assert!(parse("patch.txt -o - -f --bits 32").is_err());
+    assert!(parse("patch.txt -o - -b 12").is_err());
+    assert!(parse("patch.txt -o - --voices 8").is_err());
+    assert!(parse("patch.txt -o - --voices 4").is_err());
+    assert!(parse("patch.txt -o - --scale a.scl").is_err());
+    assert!(parse("patch.txt -o - -m song.mid --keyboard-map a.kbm").is_err());
+    assert!(parse("patch.txt -o - -m song.mid --voices 0").is_err());
+    assert!(parse("patch.txt -o - -r").is_err());
+    assert!(parse("patch.txt -o - -c 16383 -f").unwrap().is_some());
+    assert!(parse("patch.txt -o - -c 16384 -f").is_err());
+    assert!(parse("patch.txt -o - -c 20000 -b 32").is_err());
+    assert!(parse("patch.txt -o - -c 65535 -b 8").unwrap().is_some());
+    assert!(parse("patch.txt -o - -r 0.2").is_err());
+    assert!(parse("patch.txt -o - -r 1e12").is_err());
+    assert!(parse("patch.txt -o - -c 2 -r 1073741000").unwrap().is_some());
+    assert!(parse("patch.txt -o - -c 2 -r 1073741823").is_err());
+    assert!(parse("patch.txt -o - --unknown").is_err());
+    assert!(parse("patch.txt other.txt -o -").is_err());
+    assert!(parse("patch.txt").is_err());
+    assert!(parse("-o -").is_err());
+}
+
+#[test]
+fn test_render_until_silent() {
+    /// Full scale output within the bursts, given as start and end, silence
+    /// elsewhere.
+    struct Bursts {
+        bursts: Vec<(usize, usize)>,
+        position: usize,
+    }
+
+    impl SoundModule for Bursts {
+        fn reset(&mut self) {
+            self.position = 0;
+        }
+
+        fn set_sampling_parameters(&mut self, _params: &SamplingParameters) {}
+    }
+
+    impl SignalGenerator for Bursts {
+        type Output = f32;
+
+        fn next(&mut self) -> f32 {
+            let loud = self.bursts.iter().any(|&(start, end)| (start..end).contains(&self.position));
+            self.position += 1;
+            if loud { 1.0 } else { 0.0 }
+        }
+    }
+
+    let render_bursts = |bursts: Vec<(usize, usize)>, max_length, min_length, hold| {
+        render(&mut Bursts { bursts, position: 0 }, max_length, min_length, hold)
+    };
+
+    // without a hold, render the full length
+    assert_eq!(render_bursts(vec![(0, 100)], 3000, 0, None).len(), 3000);
+    // drop the silence at the end
+    let samples = render_bursts(vec![(0, 100)], 3000, 0, Some(10));
+    assert_eq!(samples.len(), 100);
+    assert!(samples.iter().all(|&sample| sample == 1.0));
+    // across block boundaries
+    assert_eq!(render_bursts(vec![(0, BLOCK_SIZE - 5)], 3000, 0, Some(10)).len(), BLOCK_SIZE - 5);
+    // shorter pauses do not stop the rendering
+    assert_eq!(render_bursts(vec![(0, 100), (120, 170)], 3000, 0, Some(30)).len(), 170);
+    assert_eq!(render_bursts(vec![(0, 100), (120, 170)], 3000, 0, Some(10)).len(), 100);
+    // nor does silence before the minimum length
+    assert_eq!(render_bursts(vec![(0, 100), (120, 170)], 3000, 150, Some(10)).len(), 170);
+    // at most the maximum length
+    assert_eq!(render_bursts(vec![(0, 5000)], 3000, 0, Some(10)).len(), 3000);
+    // silence from the start
+    assert_eq!(render_bursts(vec![], 3000, 0, Some(10)).len(), 0);
+}
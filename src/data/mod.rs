@@ -2,6 +2,7 @@
 use std::iter::FromIterator;
 use std::collections::VecDeque;
 
+#[derive(Debug, Clone)]
 pub struct SlidingWindowMax<T> {
     elements: VecDeque<T>,
     max_elements: VecDeque<T>
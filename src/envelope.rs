@@ -0,0 +1,313 @@
+//! Envelope generators for shaping the amplitude (or any other parameter) of a
+//! sound over time.
+
+use foundation::{Duration, Frequency, SignalGenerator, SoundModule, SamplingParameters};
+
+/// Construct an ADSR envelope with linear segments that restarts from zero on
+/// every trigger.
+pub fn adsr<Gate>(gate: Gate, attack: Duration, decay: Duration, sustain: f32, release: Duration) -> Adsr<Gate> where
+    Gate: SignalGenerator<Output=bool>
+{
+    Adsr::new(gate, attack, decay, sustain, release)
+}
+
+/// The shape of the envelope segments.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Curve {
+    /// Segments are straight lines.
+    Linear,
+    /// Segments approach their target exponentially, like an analog RC
+    /// envelope. The attack is concave, decay and release are convex.
+    Exponential,
+}
+
+/// What happens when the gate opens while the envelope is still active.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Trigger {
+    /// The attack restarts from zero.
+    Retrigger,
+    /// The attack continues from the current level.
+    Legato,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// A segment of the envelope, advancing the level as `base + level * coef`.
+#[derive(Debug, Copy, Clone)]
+struct Segment {
+    coef: f32,
+    base: f32,
+}
+
+impl Segment {
+    /// Overshoot of the exponential attack target, determines the curvature.
+    const ATTACK_OVERSHOOT: f32 = 0.3;
+    /// Overshoot of the exponential decay and release targets.
+    const DECAY_OVERSHOOT: f32 = 0.0001;
+
+    /// A segment reaching `to` from `from` after `num_samples` steps.
+    fn new(curve: Curve, overshoot: f32, from: f32, to: f32, num_samples: f32) -> Segment {
+        match curve {
+            Curve::Linear => Segment {
+                coef: 1.0,
+                base: (to - from) / num_samples,
+            },
+            Curve::Exponential => {
+                let target = if to > from { to + overshoot } else { to - overshoot };
+                let coef = (-(((to - from).abs() + overshoot) / overshoot).ln() / num_samples).exp();
+                Segment {
+                    coef,
+                    base: target * (1.0 - coef),
+                }
+            }
+        }
+    }
+}
+
+/// An attack-decay-sustain-release envelope driven by a gate signal.
+///
+/// When the gate opens, the level rises to one within the attack time, then
+/// falls to the sustain level within the decay time and stays there until the
+/// gate closes. It then falls to zero within the release time.
+#[derive(Debug, Clone)]
+pub struct Adsr<Gate> {
+    gate: Gate,
+    attack: Duration,
+    decay: Duration,
+    sustain: f32,
+    release: Duration,
+    curve: Curve,
+    trigger: Trigger,
+    sample_rate: Frequency,
+    stage: Stage,
+    segment: Segment,
+    level: f32,
+    /// The level at which the release segment started.
+    release_level: f32,
+    gate_open: bool,
+}
+
+impl<Gate> Adsr<Gate> where
+    Gate: SignalGenerator<Output=bool>
+{
+    pub fn new(gate: Gate, attack: Duration, decay: Duration, sustain: f32, release: Duration) -> Self {
+        Adsr {
+            gate,
+            attack,
+            decay,
+            sustain: sustain.clamp(0.0, 1.0),
+            release,
+            curve: Curve::Linear,
+            trigger: Trigger::Retrigger,
+            sample_rate: Frequency::from_hertz(f32::NAN),
+            stage: Stage::Idle,
+            segment: Segment { coef: 1.0, base: 0.0 },
+            level: 0.0,
+            release_level: 0.0,
+            gate_open: false,
+        }
+    }
+
+    pub fn with_curve(mut self, curve: Curve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn with_trigger(mut self, trigger: Trigger) -> Self {
+        self.trigger = trigger;
+        self
+    }
+}
+
+impl<Gate> Adsr<Gate> {
+    /// Return true if the envelope has completely faded out.
+    pub fn is_idle(&self) -> bool {
+        self.stage == Stage::Idle
+    }
+
+    /// Switch to the given stage, starting from the current level.
+    fn enter(&mut self, stage: Stage) {
+        self.stage = stage;
+        self.update_segment();
+    }
+
+    /// Compute the segment of the current stage so that the target is reached
+    /// at the configured time, taking into account how far the current level
+    /// already is along the way.
+    fn update_segment(&mut self) {
+        let (overshoot, target, duration) = match self.stage {
+            Stage::Idle | Stage::Sustain => return,
+            Stage::Attack =>
+                (Segment::ATTACK_OVERSHOOT, 1.0, self.attack * (1.0 - self.level)),
+            Stage::Decay => {
+                let remaining = if self.sustain < 1.0 { (self.level - self.sustain) / (1.0 - self.sustain) } else { 0.0 };
+                (Segment::DECAY_OVERSHOOT, self.sustain, self.decay * remaining)
+            },
+            Stage::Release => {
+                let remaining = if self.release_level > 0.0 { self.level / self.release_level } else { 0.0 };
+                (Segment::DECAY_OVERSHOOT, 0.0, self.release * remaining)
+            },
+        };
+        let num_samples = self.sample_rate * duration;
+        if num_samples < 1.0 {
+            // jump directly to the target within the next step
+            self.segment = Segment { coef: 0.0, base: target };
+        } else {
+            self.segment = Segment::new(self.curve, overshoot, self.level, target, num_samples);
+        }
+    }
+
+    fn step(&mut self) {
+        match self.stage {
+            Stage::Idle | Stage::Sustain => {},
+            Stage::Attack => {
+                self.level = self.segment.base + self.level * self.segment.coef;
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.enter(Stage::Decay);
+                }
+            },
+            Stage::Decay => {
+                self.level = self.segment.base + self.level * self.segment.coef;
+                if self.level <= self.sustain {
+                    self.level = self.sustain;
+                    self.enter(Stage::Sustain);
+                }
+            },
+            Stage::Release => {
+                self.level = self.segment.base + self.level * self.segment.coef;
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.enter(Stage::Idle);
+                }
+            },
+        }
+    }
+}
+
+impl<Gate: SoundModule> SoundModule for Adsr<Gate> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.gate.set_sampling_parameters(params);
+        self.sample_rate = params.sample_rate();
+        self.update_segment();
+    }
+
+    fn reset(&mut self) {
+        self.gate.reset();
+        self.stage = Stage::Idle;
+        self.level = 0.0;
+        self.release_level = 0.0;
+        self.gate_open = false;
+    }
+}
+
+impl<Gate> SignalGenerator for Adsr<Gate> where
+    Gate: SignalGenerator<Output=bool>
+{
+    type Output = f32;
+
+    fn next(&mut self) -> f32 {
+        let gate = self.gate.next();
+        if gate && !self.gate_open {
+            if self.trigger == Trigger::Retrigger {
+                self.level = 0.0;
+            }
+            self.enter(Stage::Attack);
+        } else if !gate && self.gate_open {
+            self.release_level = self.level;
+            self.enter(Stage::Release);
+        }
+        self.gate_open = gate;
+        self.step();
+        self.level
+    }
+}
+
+#[test]
+fn test_adsr_linear() {
+    use knob::Knob;
+
+    let mut gate = Knob::new(false);
+    let mut env = adsr(gate.as_generator(), Duration::from_seconds(0.4), Duration::from_seconds(0.2),
+                       0.5, Duration::from_seconds(0.5));
+    env.set_sampling_parameters(&SamplingParameters::with_rate(Frequency::from_hertz(10.0)));
+
+    let run = |env: &mut Adsr<_>, count| (0..count).map(|_| env.next()).collect::<Vec<f32>>();
+    let close = |a: Vec<f32>, b: Vec<f32>| a.iter().zip(b.iter()).all(|(x, y)| (x - y).abs() < 1e-5);
+
+    assert_eq!(run(&mut env, 2), vec![0.0, 0.0]);
+    gate.set(true);
+    assert!(close(run(&mut env, 8), vec![0.25, 0.5, 0.75, 1.0, 0.75, 0.5, 0.5, 0.5]));
+    gate.set(false);
+    assert!(close(run(&mut env, 6), vec![0.4, 0.3, 0.2, 0.1, 0.0, 0.0]));
+    assert!(env.is_idle());
+}
+
+#[test]
+fn test_adsr_exponential() {
+    use knob::Knob;
+
+    let mut gate = Knob::new(false);
+    let mut env = adsr(gate.as_generator(), Duration::from_seconds(0.1), Duration::from_seconds(0.2),
+                       0.5, Duration::from_seconds(0.3))
+        .with_curve(Curve::Exponential);
+    env.set_sampling_parameters(&SamplingParameters::with_rate(Frequency::from_hertz(1000.0)));
+
+    // each segment reaches its target within its time, give or take a sample
+    gate.set(true);
+    let levels: Vec<f32> = (0..400).map(|_| env.next()).collect();
+    assert_eq!(levels.iter().position(|&level| level == 1.0), Some(99));
+    assert!((299..=300).contains(&levels.iter().position(|&level| level == 0.5).unwrap()));
+    // the attack is concave, rising faster than a line at first
+    assert!(levels[..99].iter().enumerate().all(|(i, &level)| level > (i + 1) as f32 / 100.0));
+    // while decay and release are convex, falling faster than a line at first
+    assert!(levels[100..299].iter().enumerate().all(|(i, &level)| level < 1.0 - 0.5 * (i + 1) as f32 / 200.0));
+    gate.set(false);
+    let release: Vec<f32> = (0..310).map(|_| env.next()).collect();
+    assert!((299..=300).contains(&release.iter().position(|&level| level == 0.0).unwrap()));
+    assert!(release[..298].iter().enumerate().all(|(i, &level)| level < 0.5 * (1.0 - (i + 1) as f32 / 300.0)));
+    assert!(env.is_idle());
+}
+
+#[test]
+fn test_adsr_trigger() {
+    use knob::Knob;
+
+    for &trigger in [Trigger::Retrigger, Trigger::Legato].iter() {
+        for &curve in [Curve::Linear, Curve::Exponential].iter() {
+            let mut gate = Knob::new(true);
+            let mut env = adsr(gate.as_generator(), Duration::from_seconds(0.1), Duration::from_seconds(0.1),
+                               0.8, Duration::from_seconds(0.5))
+                .with_curve(curve)
+                .with_trigger(trigger);
+            env.set_sampling_parameters(&SamplingParameters::with_rate(Frequency::from_hertz(1000.0)));
+
+            // open the gate again halfway through the release
+            (0..300).for_each(|_| { env.next(); });
+            gate.set(false);
+            let released = (0..100).map(|_| env.next()).last().unwrap();
+            assert!(released > 0.1 && released < 0.7);
+            gate.set(true);
+            let attack: Vec<f32> = (0..300).map(|_| env.next()).collect();
+            match trigger {
+                // the attack starts over from zero
+                Trigger::Retrigger => assert!(attack[0] < 0.1),
+                // or continues from the current level without a jump
+                Trigger::Legato => {
+                    assert!(attack[0] > released && attack[0] - released < 0.05);
+                    assert!(attack.windows(2).take_while(|w| w[0] < 1.0).all(|w| w[1] >= w[0]));
+                },
+            }
+            // either way, the attack arrives at the peak and decays to the sustain level
+            assert!(attack.contains(&1.0));
+            assert_eq!(attack[299], 0.8);
+        }
+    }
+}
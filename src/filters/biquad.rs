@@ -0,0 +1,251 @@
+//! Second order IIR filters based on Robert Bristow-Johnson's "Cookbook
+//! formulae for audio EQ biquad filter coefficients".
+
+use foundation::{Filter, SignalGenerator, SoundModule, SamplingParameters, Frequency};
+use std;
+
+/// The frequency response of a biquad filter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BiquadType {
+    LowPass,
+    HighPass,
+    /// Band pass with a constant peak gain of 0 dB.
+    BandPass,
+    Notch,
+    AllPass,
+    PeakingEq,
+    LowShelf,
+    HighShelf,
+}
+
+pub fn low_pass<Freq, Q>(cutoff: Freq, q: Q) -> Biquad<Freq, Q, f32> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Q: SignalGenerator<Output=f32>
+{
+    Biquad::new(BiquadType::LowPass, cutoff, q, 0.0)
+}
+
+pub fn high_pass<Freq, Q>(cutoff: Freq, q: Q) -> Biquad<Freq, Q, f32> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Q: SignalGenerator<Output=f32>
+{
+    Biquad::new(BiquadType::HighPass, cutoff, q, 0.0)
+}
+
+pub fn band_pass<Freq, Q>(center: Freq, q: Q) -> Biquad<Freq, Q, f32> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Q: SignalGenerator<Output=f32>
+{
+    Biquad::new(BiquadType::BandPass, center, q, 0.0)
+}
+
+pub fn notch<Freq, Q>(center: Freq, q: Q) -> Biquad<Freq, Q, f32> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Q: SignalGenerator<Output=f32>
+{
+    Biquad::new(BiquadType::Notch, center, q, 0.0)
+}
+
+pub fn all_pass<Freq, Q>(center: Freq, q: Q) -> Biquad<Freq, Q, f32> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Q: SignalGenerator<Output=f32>
+{
+    Biquad::new(BiquadType::AllPass, center, q, 0.0)
+}
+
+pub fn peaking_eq<Freq, Q, Gain>(center: Freq, q: Q, gain_db: Gain) -> Biquad<Freq, Q, Gain> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Q: SignalGenerator<Output=f32>,
+    Gain: SignalGenerator<Output=f32>
+{
+    Biquad::new(BiquadType::PeakingEq, center, q, gain_db)
+}
+
+pub fn low_shelf<Freq, Q, Gain>(corner: Freq, q: Q, gain_db: Gain) -> Biquad<Freq, Q, Gain> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Q: SignalGenerator<Output=f32>,
+    Gain: SignalGenerator<Output=f32>
+{
+    Biquad::new(BiquadType::LowShelf, corner, q, gain_db)
+}
+
+pub fn high_shelf<Freq, Q, Gain>(corner: Freq, q: Q, gain_db: Gain) -> Biquad<Freq, Q, Gain> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Q: SignalGenerator<Output=f32>,
+    Gain: SignalGenerator<Output=f32>
+{
+    Biquad::new(BiquadType::HighShelf, corner, q, gain_db)
+}
+
+/// Normalized coefficients of the transfer function
+/// `(b0 + b1/z + b2/z^2) / (1 + a1/z + a2/z^2)`.
+#[derive(Debug, Copy, Clone)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    /// Coefficients of a filter passing the signal unchanged.
+    fn identity() -> Self {
+        Coefficients { b0: 1.0, b1: 0.0, b2: 0.0, a1: 0.0, a2: 0.0 }
+    }
+
+    fn compute(kind: BiquadType, frequency: f32, q: f32, gain_db: f32, sample_rate: f32) -> Self {
+        // computing in double precision avoids instabilities at low frequencies
+        let w0 = 2.0 * std::f64::consts::PI * (frequency as f64 / sample_rate as f64).clamp(1e-6, 0.4999);
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * (q as f64).max(1e-3));
+        let a = 10f64.powf(gain_db as f64 / 40.0);
+        let sqrt_a_alpha = 2.0 * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match kind {
+            BiquadType::LowPass =>
+                ((1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::HighPass =>
+                ((1.0 + cos) / 2.0, -(1.0 + cos), (1.0 + cos) / 2.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::BandPass =>
+                (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::Notch =>
+                (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::AllPass =>
+                (1.0 - alpha, -2.0 * cos, 1.0 + alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            BiquadType::PeakingEq =>
+                (1.0 + alpha * a, -2.0 * cos, 1.0 - alpha * a, 1.0 + alpha / a, -2.0 * cos, 1.0 - alpha / a),
+            BiquadType::LowShelf => (
+                a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha),
+                2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha,
+                -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                (a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha,
+            ),
+            BiquadType::HighShelf => (
+                a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a_alpha),
+                -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a_alpha),
+                (a + 1.0) - (a - 1.0) * cos + sqrt_a_alpha,
+                2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                (a + 1.0) - (a - 1.0) * cos - sqrt_a_alpha,
+            ),
+        };
+
+        Coefficients {
+            b0: (b0 / a0) as f32,
+            b1: (b1 / a0) as f32,
+            b2: (b2 / a0) as f32,
+            a1: (a1 / a0) as f32,
+            a2: (a2 / a0) as f32,
+        }
+    }
+}
+
+/// A biquad filter whose frequency, quality factor and gain (in dB, only used
+/// by the peaking and shelving types) can be modulated per sample. The
+/// coefficients are only recomputed when one of the parameters changes.
+#[derive(Debug, Clone)]
+pub struct Biquad<Freq, Q, Gain> {
+    kind: BiquadType,
+    frequency: Freq,
+    q: Q,
+    gain_db: Gain,
+    sample_rate: Frequency,
+    /// The parameters the current coefficients were computed for.
+    parameters: (f32, f32, f32),
+    coefficients: Coefficients,
+    /// The last two inputs.
+    x: [f32; 2],
+    /// The last two outputs.
+    y: [f32; 2],
+}
+
+impl<Freq, Q, Gain> Biquad<Freq, Q, Gain> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Q: SignalGenerator<Output=f32>,
+    Gain: SignalGenerator<Output=f32>
+{
+    pub fn new(kind: BiquadType, frequency: Freq, q: Q, gain_db: Gain) -> Self {
+        Biquad {
+            kind,
+            frequency,
+            q,
+            gain_db,
+            sample_rate: Frequency::from_hertz(f32::NAN),
+            parameters: (f32::NAN, f32::NAN, f32::NAN),
+            coefficients: Coefficients::identity(),
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn update_coefficients(&mut self) {
+        let parameters = (self.frequency.next().to_hertz(), self.q.next(), self.gain_db.next());
+        if parameters != self.parameters {
+            self.parameters = parameters;
+            self.coefficients = Coefficients::compute(self.kind, parameters.0, parameters.1,
+                                                      parameters.2, self.sample_rate.to_hertz());
+        }
+    }
+}
+
+impl<Freq, Q, Gain> SoundModule for Biquad<Freq, Q, Gain> where
+    Freq: SoundModule,
+    Q: SoundModule,
+    Gain: SoundModule
+{
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.frequency.set_sampling_parameters(params);
+        self.q.set_sampling_parameters(params);
+        self.gain_db.set_sampling_parameters(params);
+        self.sample_rate = params.sample_rate();
+        // force recomputation with the new sample rate
+        self.parameters = (f32::NAN, f32::NAN, f32::NAN);
+    }
+
+    fn reset(&mut self) {
+        self.frequency.reset();
+        self.q.reset();
+        self.gain_db.reset();
+        self.x = [0.0; 2];
+        self.y = [0.0; 2];
+    }
+}
+
+impl<Freq, Q, Gain> Filter for Biquad<Freq, Q, Gain> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Q: SignalGenerator<Output=f32>,
+    Gain: SignalGenerator<Output=f32>
+{
+    type Input = f32;
+    type Output = f32;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        self.update_coefficients();
+        let c = &self.coefficients;
+        // direct form I keeps working when the coefficients change per sample
+        let output = c.b0 * input + c.b1 * self.x[0] + c.b2 * self.x[1] - c.a1 * self.y[0] - c.a2 * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+#[test]
+fn test_biquad_dc_response() {
+    use foundation::generator::constant;
+
+    let dc_gain = |filter: &mut dyn Filter<Input=f32, Output=f32>| {
+        filter.set_sampling_parameters(&SamplingParameters::audio_cd());
+        (0..10000).map(|_| filter.filter(1.0)).last().unwrap()
+    };
+    let cutoff = || constant(Frequency::from_hertz(1000.0));
+    assert!((dc_gain(&mut low_pass(cutoff(), 0.707)) - 1.0).abs() < 1e-3);
+    assert!(dc_gain(&mut high_pass(cutoff(), 0.707)).abs() < 1e-3);
+    assert!(dc_gain(&mut band_pass(cutoff(), 0.707)).abs() < 1e-3);
+    assert!((dc_gain(&mut notch(cutoff(), 0.707)) - 1.0).abs() < 1e-3);
+    assert!((dc_gain(&mut low_shelf(cutoff(), 0.707, 6.0)) - 10f32.powf(6.0 / 20.0)).abs() < 1e-3);
+    assert!((dc_gain(&mut high_shelf(cutoff(), 0.707, 6.0)) - 1.0).abs() < 1e-3);
+}
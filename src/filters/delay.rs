@@ -2,10 +2,13 @@
 
 use std;
 
-use foundation::{Frequency, Duration, Filter, Sample, SoundModule, SamplingParameters};
+use foundation::{Frequency, Duration, Filter, Sample, SignalGenerator, SoundModule, SamplingParameters};
+use foundation::filter::process_buffered;
+use foundation::generator::BLOCK_SIZE;
 
+/// A fixed-size buffer where the oldest element is overwritten by the newest.
 #[derive(Debug, Clone)]
-struct RingBuffer<S> {
+pub(crate) struct RingBuffer<S> {
     buffer: std::vec::Vec<S>,
     index: usize
 }
@@ -13,36 +16,65 @@ struct RingBuffer<S> {
 impl<S> RingBuffer<S> where
     S: Sample
 {
-    fn new(size: usize) -> Self {
+    pub(crate) fn new(size: usize) -> Self {
         RingBuffer {
             buffer: vec![S::equilibrium(); size],
             index: 0
         }
     }
 
-    fn resize(&mut self, new_size: usize) {
+    pub(crate) fn resize(&mut self, new_size: usize) {
         self.buffer.resize(new_size, S::equilibrium());
         self.index = self.index % self.buffer.len();
     }
 
-    fn current_mut(&mut self) -> &mut S {
+    pub(crate) fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Return the element that was shifted in `age` steps ago, where an age
+    /// of one refers to the most recent one.
+    pub(crate) fn get(&self, age: usize) -> S {
+        debug_assert!(age >= 1 && age <= self.buffer.len());
+        self.buffer[(self.index + self.buffer.len() - age) % self.buffer.len()]
+    }
+
+    pub(crate) fn current_mut(&mut self) -> &mut S {
         &mut self.buffer[self.index]
     }
 
-    fn forward(&mut self) {
+    pub(crate) fn forward(&mut self) {
         self.index += 1;
         if self.index == self.buffer.len() {
             self.index = 0;
         }
     }
 
-    fn shift(&mut self, in_value: S) -> S {
+    pub(crate) fn shift(&mut self, in_value: S) -> S {
         let out_value = std::mem::replace(self.current_mut(), in_value);
         self.forward();
         out_value
     }
 
-    fn reset(&mut self) {
+    /// Walk through the buffer once per input element like `forward`, calling
+    /// a function with the current element and the input to compute the
+    /// output. The buffer is traversed in contiguous slices.
+    pub(crate) fn process_slice<F>(&mut self, mut input: &[S], mut output: &mut [S], mut fun: F) where
+        F: FnMut(&mut S, S) -> S
+    {
+        while !input.is_empty() {
+            let len = input.len().min(self.buffer.len() - self.index);
+            let current = &mut self.buffer[self.index..self.index + len];
+            for ((element, &input), output) in current.iter_mut().zip(input.iter()).zip(output.iter_mut()) {
+                *output = fun(element, input);
+            }
+            input = &input[len..];
+            output = &mut output[len..];
+            self.index = (self.index + len) % self.buffer.len();
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
         self.index = 0;
         for x in self.buffer.iter_mut() {
             *x = S::equilibrium()
@@ -96,6 +128,17 @@ impl<S> Filter for Delay<S> where
     fn filter(&mut self, input: Self::Input) -> Self::Output {
         self.delay_buffer.shift(input)
     }
+
+    fn process(&mut self, input: &[S], output: &mut [S]) {
+        debug_assert_eq!(input.len(), output.len());
+        self.delay_buffer.process_slice(input, output, |element, input| std::mem::replace(element, input));
+    }
+
+    fn process_generated<G>(&mut self, generator: &mut G, output: &mut [S]) where
+        G: SignalGenerator<Output=S>
+    {
+        process_buffered(self, generator, output)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -149,4 +192,224 @@ impl<S> Filter for Echo<S> where
         self.delay_buffer.forward();
         current
     }
+
+    fn process(&mut self, input: &[S], output: &mut [S]) {
+        debug_assert_eq!(input.len(), output.len());
+        let dampening = self.dampening;
+        self.delay_buffer.process_slice(input, output, |element, input| {
+            let current = *element;
+            *element = (current + input) * dampening;
+            current
+        });
+    }
+
+    fn process_generated<G>(&mut self, generator: &mut G, output: &mut [S]) where
+        G: SignalGenerator<Output=S>
+    {
+        process_buffered(self, generator, output)
+    }
+}
+
+/// How a delay line reads between two samples.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Cheap, but dampens high frequencies when the delay is not modulated.
+    Linear,
+    /// Four-point Hermite interpolation.
+    Cubic,
+    /// First order allpass interpolation, which leaves the magnitude response
+    /// flat. Best suited for slowly modulated delays.
+    AllPass,
+}
+
+/// A delay line that can be read at fractional positions, with room for a
+/// fixed maximum delay.
+#[derive(Debug, Clone)]
+pub(crate) struct DelayLine {
+    interpolation: Interpolation,
+    buffer: RingBuffer<f32>,
+    /// The previous output of the allpass interpolator.
+    allpass_state: f32,
+}
+
+impl DelayLine {
+    pub(crate) fn new(interpolation: Interpolation) -> Self {
+        DelayLine {
+            interpolation,
+            buffer: RingBuffer::new(4),
+            allpass_state: 0.0,
+        }
+    }
+
+    /// The shortest delay that can be read.
+    fn min_delay(&self) -> f32 {
+        // cubic interpolation also needs the sample after the integer delay
+        if self.interpolation == Interpolation::Cubic { 2.0 } else { 1.0 }
+    }
+
+    /// Make room for a delay of up to `max_samples`, but at least the minimum
+    /// delay.
+    pub(crate) fn resize(&mut self, max_samples: f32) {
+        let max_samples = if max_samples.is_finite() { max_samples.max(self.min_delay()) } else { self.min_delay() };
+        // room for the maximum delay plus the neighbours needed for interpolation
+        self.buffer.resize((max_samples.ceil() as usize).saturating_add(3));
+    }
+
+    /// Read the delay line at a fractional number of samples in the past.
+    pub(crate) fn read(&mut self, delay: f32) -> f32 {
+        let min_delay = self.min_delay();
+        let max_delay = (self.buffer.len() - 2) as f32;
+        // e.g. before the sample rate is known, the delay is not a number
+        let delay = if delay.is_finite() { delay.clamp(min_delay, max_delay) } else { min_delay };
+        let index = delay.floor() as usize;
+        let frac = delay - delay.floor();
+
+        match self.interpolation {
+            Interpolation::Linear => {
+                let (newer, older) = (self.buffer.get(index), self.buffer.get(index + 1));
+                newer + frac * (older - newer)
+            },
+            Interpolation::Cubic => {
+                let p0 = self.buffer.get(index - 1);
+                let p1 = self.buffer.get(index);
+                let p2 = self.buffer.get(index + 1);
+                let p3 = self.buffer.get(index + 2);
+                let c1 = 0.5 * (p2 - p0);
+                let c2 = p0 - 2.5 * p1 + 2.0 * p2 - 0.5 * p3;
+                let c3 = 0.5 * (p3 - p0) + 1.5 * (p1 - p2);
+                ((c3 * frac + c2) * frac + c1) * frac + p1
+            },
+            Interpolation::AllPass => {
+                let (newer, older) = (self.buffer.get(index), self.buffer.get(index + 1));
+                let eta = (1.0 - frac) / (1.0 + frac);
+                self.allpass_state = eta * (newer - self.allpass_state) + older;
+                self.allpass_state
+            },
+        }
+    }
+
+    pub(crate) fn write(&mut self, value: f32) {
+        self.buffer.shift(value);
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.buffer.reset();
+        self.allpass_state = 0.0;
+    }
+}
+
+/// A delay line whose delay time is read from a signal every sample, allowing
+/// for fractional delays and smooth modulation, e.g. for chorus, flanger and
+/// vibrato effects. The delay time is limited to the maximum given when
+/// constructing the delay line.
+///
+/// Part of the output is fed back into the delay line.
+#[derive(Debug, Clone)]
+pub struct ModulatedDelay<Time> {
+    delay_time: Time,
+    max_delay: Duration,
+    feedback: f32,
+    sample_rate: Frequency,
+    line: DelayLine,
+}
+
+impl<Time> ModulatedDelay<Time> where
+    Time: SignalGenerator<Output=Duration>
+{
+    pub fn new(delay_time: Time, max_delay: Duration, interpolation: Interpolation) -> Self {
+        ModulatedDelay {
+            delay_time,
+            max_delay,
+            feedback: 0.0,
+            sample_rate: Frequency::from_hertz(f32::NAN),
+            line: DelayLine::new(interpolation),
+        }
+    }
+
+    /// Set the fraction of the output that is fed back into the delay line.
+    pub fn with_feedback(mut self, feedback: f32) -> Self {
+        self.feedback = feedback;
+        self
+    }
+}
+
+impl<Time> SoundModule for ModulatedDelay<Time> where
+    Time: SoundModule
+{
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.delay_time.set_sampling_parameters(params);
+        self.sample_rate = params.sample_rate();
+        self.line.resize(self.sample_rate * self.max_delay);
+    }
+
+    fn reset(&mut self) {
+        self.delay_time.reset();
+        self.line.reset();
+    }
+}
+
+impl<Time> Filter for ModulatedDelay<Time> where
+    Time: SignalGenerator<Output=Duration>
+{
+    type Input = f32;
+    type Output = f32;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let delay = self.sample_rate * self.delay_time.next();
+        let output = self.line.read(delay);
+        self.line.write(input + output * self.feedback);
+        output
+    }
+
+    fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        debug_assert_eq!(input.len(), output.len());
+        let mut delay_times = [Duration::from_seconds(0.0); BLOCK_SIZE];
+        for (input, output) in input.chunks(BLOCK_SIZE).zip(output.chunks_mut(BLOCK_SIZE)) {
+            let delay_times = &mut delay_times[..input.len()];
+            self.delay_time.fill(delay_times);
+            for ((output, &input), &delay_time) in output.iter_mut().zip(input.iter()).zip(delay_times.iter()) {
+                *output = self.line.read(self.sample_rate * delay_time);
+                self.line.write(input + *output * self.feedback);
+            }
+        }
+    }
+
+    fn process_generated<G>(&mut self, generator: &mut G, output: &mut [f32]) where
+        G: SignalGenerator<Output=f32>
+    {
+        process_buffered(self, generator, output)
+    }
+}
+
+#[test]
+fn test_modulated_delay() {
+    use foundation::generator::constant;
+
+    let params = SamplingParameters::with_rate(Frequency::from_hertz(10.0));
+    let impulse = |i| if i == 0 { 1.0 } else { 0.0 };
+
+    let mut linear = ModulatedDelay::new(constant(Duration::from_seconds(0.25)), Duration::from_seconds(1.0), Interpolation::Linear);
+    linear.set_sampling_parameters(&params);
+    let output: Vec<f32> = (0..5).map(|i| linear.filter(impulse(i))).collect();
+    assert_eq!(output, vec![0.0, 0.0, 0.5, 0.5, 0.0]);
+
+    // integer delays are exact for all interpolation modes, even with feedback
+    for &interpolation in [Interpolation::Linear, Interpolation::Cubic, Interpolation::AllPass].iter() {
+        let mut delay = ModulatedDelay::new(constant(Duration::from_seconds(0.3)), Duration::from_seconds(1.0), interpolation)
+            .with_feedback(0.5);
+        delay.set_sampling_parameters(&params);
+        let output: Vec<f32> = (0..7).map(|i| delay.filter(impulse(i))).collect();
+        assert_eq!(output, vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.5]);
+
+        // filtering before the sampling parameters are set must not panic
+        let mut unprepared = ModulatedDelay::new(constant(Duration::from_seconds(0.3)), Duration::from_seconds(1.0), interpolation);
+        assert!((0..7).map(|i| unprepared.filter(impulse(i))).all(f32::is_finite));
+
+        // nor must a maximum delay shorter than the minimum of the interpolation
+        for &max_delay in [0.0, -1.0, std::f32::NAN].iter() {
+            let mut short = ModulatedDelay::new(constant(Duration::from_seconds(0.0)), Duration::from_seconds(max_delay), interpolation);
+            short.set_sampling_parameters(&params);
+            assert!((0..7).map(|i| short.filter(impulse(i))).all(f32::is_finite));
+        }
+    }
 }
@@ -1,7 +1,11 @@
 //! A module for filters that distort a signal.
 
 use foundation::Filter;
-use foundation::{SoundModule, SamplingParameters};
+use foundation::{Frequency, SignalGenerator, SoundModule, SamplingParameters};
+use foundation::generator::{constant, Const};
+use noise::{white_noise, Noise, White};
+use rand::XorShiftRng;
+use super::biquad::{low_pass, Biquad};
 use super::limiter::{hard_limit};
 
 #[derive(Debug, Clone)]
@@ -34,3 +38,335 @@ impl Filter for Overdrive
         hard_limit(input * self.factor)
     }
 }
+
+/// The transfer curve of a waveshaper.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Shape {
+    /// Hyperbolic tangent, smoothly saturating towards `-1` and `1`.
+    Tanh,
+    /// Cubic soft clipping, reaching `-1` and `1` at the same inputs and staying
+    /// there.
+    SoftClip,
+    /// Asymmetric saturation like an overdriven tube stage, where the negative
+    /// half saturates earlier (at `-0.5`) than the positive half, adding even
+    /// harmonics.
+    Tube,
+    /// Folds everything beyond `-1` and `1` back into the range, producing
+    /// bright, metallic sounds at high drive levels.
+    Foldback,
+    /// A weighted sum of Chebyshev polynomials, where the n-th weight
+    /// determines the amplitude of the n-th harmonic of a full-scale sine.
+    /// Inputs are limited to the range from `-1` to `1`.
+    Chebyshev(Vec<f32>),
+    /// A transfer curve given as points evenly spaced between the inputs `-1`
+    /// and `1`, interpolated linearly. Inputs are limited to that range.
+    Table(Vec<f32>),
+}
+
+impl Shape {
+    pub fn apply(&self, x: f32) -> f32 {
+        match *self {
+            Shape::Tanh => x.tanh(),
+            Shape::SoftClip => {
+                let x = x.clamp(-1.0, 1.0);
+                1.5 * x - 0.5 * x * x * x
+            },
+            Shape::Tube => if x >= 0.0 { x.tanh() } else { 0.5 * (2.0 * x).tanh() },
+            Shape::Foldback => ((x - 1.0).rem_euclid(4.0) - 2.0).abs() - 1.0,
+            Shape::Chebyshev(ref weights) => {
+                let x = x.clamp(-1.0, 1.0);
+                // T_0 = 1, T_1 = x, T_n+1 = 2x T_n - T_n-1
+                let (mut previous, mut current) = (1.0, x);
+                let mut sum = 0.0;
+                for &weight in weights {
+                    sum += weight * current;
+                    let next = 2.0 * x * current - previous;
+                    previous = current;
+                    current = next;
+                }
+                sum
+            },
+            Shape::Table(ref points) => {
+                if points.len() < 2 {
+                    return points.first().cloned().unwrap_or(0.0);
+                }
+                let position = 0.5 * (x.clamp(-1.0, 1.0) + 1.0) * (points.len() - 1) as f32;
+                let index = (position.floor() as usize).min(points.len() - 2);
+                let frac = position - index as f32;
+                points[index] + frac * (points[index + 1] - points[index])
+            },
+        }
+    }
+}
+
+/// Distorts a signal by passing it through a nonlinear transfer curve.
+///
+/// The input is multiplied by the drive and offset by the bias before being
+/// shaped, and the result is scaled by the output gain. The offset the bias
+/// causes at silence is removed again, so that only the asymmetry remains.
+///
+/// Nonlinearities create harmonics above the Nyquist frequency, which alias
+/// back into the audible range. Running the shaper at a multiple of the
+/// sample rate and filtering before going back reduces that aliasing.
+#[derive(Debug, Clone)]
+pub struct Waveshaper {
+    shape: Shape,
+    drive: f32,
+    bias: f32,
+    output_gain: f32,
+    oversampling: usize,
+    /// Anti-aliasing filters before and after the shaper when oversampling.
+    anti_aliasing: Option<(AntiAliasing, AntiAliasing)>,
+}
+
+impl Waveshaper {
+    pub fn new(shape: Shape) -> Self {
+        Waveshaper {
+            shape,
+            drive: 1.0,
+            bias: 0.0,
+            output_gain: 1.0,
+            oversampling: 1,
+            anti_aliasing: None,
+        }
+    }
+
+    pub fn with_drive(mut self, drive: f32) -> Self {
+        self.drive = drive;
+        self
+    }
+
+    pub fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    pub fn with_output_gain(mut self, output_gain: f32) -> Self {
+        self.output_gain = output_gain;
+        self
+    }
+
+    /// Run the shaper at the given multiple of the sample rate.
+    pub fn with_oversampling(mut self, factor: usize) -> Self {
+        self.oversampling = factor.max(1);
+        self
+    }
+}
+
+impl SoundModule for Waveshaper {
+    fn reset(&mut self) {
+        if let Some((ref mut up, ref mut down)) = self.anti_aliasing {
+            up.reset();
+            down.reset();
+        }
+    }
+
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.anti_aliasing = if self.oversampling > 1 {
+            let filter = AntiAliasing::new(params, self.oversampling);
+            Some((filter.clone(), filter))
+        } else {
+            None
+        };
+    }
+}
+
+impl Filter for Waveshaper {
+    type Input = f32;
+    type Output = f32;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let Waveshaper { ref shape, drive, bias, output_gain, oversampling, ref mut anti_aliasing } = *self;
+        let offset = shape.apply(bias);
+        let transfer = |x: f32| output_gain * (shape.apply(drive * x + bias) - offset);
+
+        match *anti_aliasing {
+            Some((ref mut up, ref mut down)) => {
+                // insert zeros between the samples, keeping the energy the same
+                let mut output = 0.0;
+                for step in 0..oversampling {
+                    let stuffed = if step == 0 { input * oversampling as f32 } else { 0.0 };
+                    output = down.filter(transfer(up.filter(stuffed)));
+                }
+                output
+            },
+            None => transfer(input),
+        }
+    }
+}
+
+/// An eighth order Butterworth low pass removing everything above the original
+/// Nyquist frequency at the oversampled rate.
+#[derive(Debug, Clone)]
+struct AntiAliasing {
+    stages: Vec<Biquad<Const<Frequency>, f32, f32>>,
+}
+
+impl AntiAliasing {
+    /// Quality factors of the stages of an eighth order Butterworth filter.
+    const BUTTERWORTH_Q: [f32; 4] = [0.509_796, 0.601_345, 0.899_976, 2.562_915];
+    /// Cutoff frequency relative to the original sample rate.
+    const RELATIVE_CUTOFF: f32 = 0.45;
+
+    fn new(params: &SamplingParameters, factor: usize) -> Self {
+        let cutoff = constant(params.sample_rate() * Self::RELATIVE_CUTOFF);
+        let mut stages: Vec<_> = Self::BUTTERWORTH_Q.iter().map(|&q| low_pass(cutoff, q)).collect();
+        let oversampled = SamplingParameters::with_rate(params.sample_rate() * factor as f32);
+        stages.iter_mut().for_each(|stage| stage.set_sampling_parameters(&oversampled));
+        AntiAliasing { stages }
+    }
+
+    #[inline(always)]
+    fn filter(&mut self, input: f32) -> f32 {
+        self.stages.iter_mut().fold(input, |signal, stage| stage.filter(signal))
+    }
+
+    fn reset(&mut self) {
+        self.stages.iter_mut().for_each(SoundModule::reset);
+    }
+}
+
+#[test]
+fn test_waveshaper() {
+    let shaper = |shape: Shape| Waveshaper::new(shape);
+    assert_eq!(shaper(Shape::Tanh).with_drive(2.0).filter(0.5), 1f32.tanh());
+    assert_eq!(shaper(Shape::SoftClip).filter(2.0), 1.0);
+    assert_eq!(shaper(Shape::Foldback).filter(1.5), 0.5);
+    assert_eq!(shaper(Shape::Foldback).filter(-3.5), 0.5);
+    assert!(shaper(Shape::Tube).filter(-10.0) > -0.51);
+    assert_eq!(shaper(Shape::Table(vec![-1.0, 0.0, 0.5])).filter(0.5), 0.25);
+    // the second Chebyshev polynomial doubles the frequency of a sine, its
+    // offset at silence is removed
+    let angle = 0.3f32;
+    let chebyshev = shaper(Shape::Chebyshev(vec![0.0, 1.0])).filter(angle.cos());
+    assert!((chebyshev - (2.0 * angle).cos() - 1.0).abs() < 1e-5);
+    // the bias does not cause an offset at silence
+    assert_eq!(shaper(Shape::Tube).with_bias(0.3).with_output_gain(0.5).filter(0.0), 0.0);
+
+    // slow signals pass the anti-aliasing filters
+    let mut oversampled = shaper(Shape::Tanh).with_oversampling(4);
+    oversampled.set_sampling_parameters(&SamplingParameters::audio_cd());
+    let dc = (0..1000).map(|_| oversampled.filter(0.5)).last().unwrap();
+    assert!((dc - 0.5f32.tanh()).abs() < 1e-4);
+}
+
+/// Reduces the resolution of a signal to the given number of bits, producing
+/// the grainy sound of early digital audio.
+///
+/// With dither enabled, triangular noise of one quantization step is added
+/// before rounding, which turns the distortion of quiet signals into a noise
+/// floor.
+#[derive(Debug, Clone)]
+pub struct Bitcrusher {
+    bits: u32,
+    dither: bool,
+    /// The white noise the dither is made of, which repeats after a reset.
+    noise: Noise<White, XorShiftRng>,
+}
+
+impl Bitcrusher {
+    pub fn new(bits: u32) -> Self {
+        Bitcrusher {
+            bits: bits.clamp(1, 24),
+            dither: false,
+            noise: white_noise(),
+        }
+    }
+
+    pub fn with_dither(mut self, dither: bool) -> Self {
+        self.dither = dither;
+        self
+    }
+}
+
+impl SoundModule for Bitcrusher {
+    fn reset(&mut self) {
+        self.noise.reset();
+    }
+
+    fn set_sampling_parameters(&mut self, _params: &SamplingParameters) {}
+}
+
+impl Filter for Bitcrusher {
+    type Input = f32;
+    type Output = f32;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let steps = (1u32 << (self.bits - 1)) as f32;
+        // the average of two white noise samples has a triangular distribution
+        let dither = if self.dither { 0.5 * (self.noise.next() + self.noise.next()) } else { 0.0 };
+        ((input * steps + dither).round() / steps).clamp(-1.0, 1.0)
+    }
+}
+
+/// Reduces the sample rate of a signal by holding each sample until the next
+/// one is due at the given rate, which can be swept for aliasing effects.
+#[derive(Debug, Clone)]
+pub struct Decimator<Rate> {
+    rate: Rate,
+    sample_rate: Frequency,
+    /// Fraction of the hold period that has passed.
+    phase: f32,
+    held: f32,
+}
+
+impl<Rate> Decimator<Rate> where
+    Rate: SignalGenerator<Output=Frequency>
+{
+    pub fn new(rate: Rate) -> Self {
+        Decimator {
+            rate,
+            sample_rate: Frequency::from_hertz(f32::NAN),
+            phase: 1.0,
+            held: 0.0,
+        }
+    }
+}
+
+impl<Rate: SoundModule> SoundModule for Decimator<Rate> {
+    fn reset(&mut self) {
+        self.rate.reset();
+        self.phase = 1.0;
+        self.held = 0.0;
+    }
+
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.rate.set_sampling_parameters(params);
+        self.sample_rate = params.sample_rate();
+    }
+}
+
+impl<Rate> Filter for Decimator<Rate> where
+    Rate: SignalGenerator<Output=Frequency>
+{
+    type Input = f32;
+    type Output = f32;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        if self.phase >= 1.0 {
+            self.phase = self.phase.fract();
+            self.held = input;
+        }
+        self.phase += (self.rate.next() / self.sample_rate).clamp(0.0, 1.0);
+        self.held
+    }
+}
+
+#[test]
+fn test_lofi() {
+    let mut crusher = Bitcrusher::new(2);
+    assert_eq!([0.3, 0.2, -0.8, 1.0].iter().map(|&x| crusher.filter(x)).collect::<Vec<f32>>(),
+               vec![0.5, 0.0, -1.0, 1.0]);
+    // dither only moves the signal by up to one step
+    let mut dithered = Bitcrusher::new(8).with_dither(true);
+    let first: Vec<f32> = (0..1000).map(|_| dithered.filter(0.3)).collect();
+    assert!(first.iter().all(|&x| (x - 0.3).abs() <= 1.5 / 128.0));
+    // and repeats after a reset, like the noise it is made of
+    dithered.reset();
+    assert_eq!((0..1000).map(|_| dithered.filter(0.3)).collect::<Vec<f32>>(), first);
+
+    let mut decimator = Decimator::new(constant(Frequency::from_hertz(2.5)));
+    decimator.set_sampling_parameters(&SamplingParameters::with_rate(Frequency::from_hertz(10.0)));
+    let output: Vec<f32> = (0..10).map(|i| decimator.filter(i as f32)).collect();
+    assert_eq!(output, vec![0.0, 0.0, 0.0, 0.0, 4.0, 4.0, 4.0, 4.0, 8.0, 8.0]);
+}
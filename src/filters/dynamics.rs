@@ -0,0 +1,494 @@
+//! Filters controlling the dynamic range of a signal by adjusting its gain
+//! depending on the level of the signal itself or of a sidechain signal.
+
+use foundation::{Duration, Filter, Frequency, SignalGenerator, SoundModule, SamplingParameters};
+
+/// Level in dB that silence is mapped to, avoiding infinities.
+const MIN_LEVEL_DB: f32 = -120.0;
+/// Averaging time of the RMS level detection.
+const RMS_WINDOW: f32 = 0.01;
+/// Release time of the peak level detection, long enough to bridge the gaps
+/// between the peaks of low frequencies.
+const PEAK_RELEASE: f32 = 0.1;
+
+/// Convert a level in dB to a linear gain factor.
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// Convert a linear gain factor to a level in dB.
+pub fn gain_to_db(gain: f32) -> f32 {
+    (20.0 * gain.abs().log10()).max(MIN_LEVEL_DB)
+}
+
+/// How the level of the signal controlling the gain is measured.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Detection {
+    /// The peaks of the signal, following rises immediately to react to short
+    /// transients, but falling slowly so that the level does not drop at every
+    /// zero crossing.
+    Peak,
+    /// The root mean square over a short window, following the perceived
+    /// loudness more closely.
+    Rms,
+}
+
+/// Coefficient of a one-pole smoother reaching about 63% of a step within the
+/// given time.
+pub(crate) fn smoothing_coefficient(time: Duration, sample_rate: Frequency) -> f32 {
+    let num_samples = sample_rate * time;
+    if num_samples < 1.0 { 0.0 } else { (-1.0 / num_samples).exp() }
+}
+
+/// Measures the level of a signal in dB.
+#[derive(Debug, Clone)]
+pub(crate) struct LevelDetector {
+    detection: Detection,
+    rms_coef: f32,
+    mean_square: f32,
+    peak_coef: f32,
+    peak: f32,
+}
+
+impl LevelDetector {
+    pub(crate) fn new(detection: Detection) -> Self {
+        LevelDetector {
+            detection,
+            rms_coef: 0.0,
+            mean_square: 0.0,
+            peak_coef: 0.0,
+            peak: 0.0,
+        }
+    }
+
+    pub(crate) fn set_sample_rate(&mut self, sample_rate: Frequency) {
+        self.rms_coef = smoothing_coefficient(Duration::from_seconds(RMS_WINDOW), sample_rate);
+        self.peak_coef = smoothing_coefficient(Duration::from_seconds(PEAK_RELEASE), sample_rate);
+    }
+
+    pub(crate) fn level_db(&mut self, input: f32) -> f32 {
+        match self.detection {
+            Detection::Peak => {
+                self.peak = input.abs().max(self.peak * self.peak_coef);
+                gain_to_db(self.peak)
+            },
+            Detection::Rms => {
+                self.mean_square = input * input + self.rms_coef * (self.mean_square - input * input);
+                gain_to_db(self.mean_square.sqrt())
+            }
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.mean_square = 0.0;
+        self.peak = 0.0;
+    }
+}
+
+/// A feed-forward compressor reducing the gain of signals above the threshold.
+///
+/// Above the threshold (in dB), every `ratio` dB of input level only produce
+/// one dB of output level. Within the soft knee (width in dB) around the
+/// threshold, the ratio gradually increases. The gain reduction follows
+/// changes within the attack and release times. The makeup gain (in dB) is
+/// applied afterwards to restore the loudness.
+///
+/// By default, the level of the input controls the gain. When a sidechain is
+/// set, its level is used instead, e.g. for ducking a bass line whenever the
+/// kick drum hits.
+#[derive(Debug, Clone)]
+pub struct Compressor<Side> {
+    threshold: f32,
+    ratio: f32,
+    knee: f32,
+    attack: Duration,
+    release: Duration,
+    makeup_gain: f32,
+    sidechain: Option<Side>,
+    detector: LevelDetector,
+    attack_coef: f32,
+    release_coef: f32,
+    /// The current (smoothed) gain reduction in dB, always non-positive.
+    gain_reduction: f32,
+}
+
+impl Compressor<f32> {
+    pub fn new(threshold: f32, ratio: f32) -> Self {
+        Compressor {
+            threshold,
+            ratio: ratio.max(1.0),
+            knee: 0.0,
+            attack: Duration::from_seconds(0.01),
+            release: Duration::from_seconds(0.1),
+            makeup_gain: 0.0,
+            sidechain: None,
+            detector: LevelDetector::new(Detection::Peak),
+            attack_coef: 0.0,
+            release_coef: 0.0,
+            gain_reduction: 0.0,
+        }
+    }
+}
+
+impl<Side> Compressor<Side> where
+    Side: SignalGenerator<Output=f32>
+{
+    pub fn with_knee(mut self, knee: f32) -> Self {
+        self.knee = knee.max(0.0);
+        self
+    }
+
+    pub fn with_attack(mut self, attack: Duration) -> Self {
+        self.attack = attack;
+        self
+    }
+
+    pub fn with_release(mut self, release: Duration) -> Self {
+        self.release = release;
+        self
+    }
+
+    pub fn with_makeup_gain(mut self, makeup_gain: f32) -> Self {
+        self.makeup_gain = makeup_gain;
+        self
+    }
+
+    pub fn with_detection(mut self, detection: Detection) -> Self {
+        self.detector = LevelDetector::new(detection);
+        self
+    }
+
+    /// Control the gain by the level of the given signal instead of the input.
+    pub fn with_sidechain<S>(self, sidechain: S) -> Compressor<S> where
+        S: SignalGenerator<Output=f32>
+    {
+        Compressor {
+            threshold: self.threshold,
+            ratio: self.ratio,
+            knee: self.knee,
+            attack: self.attack,
+            release: self.release,
+            makeup_gain: self.makeup_gain,
+            sidechain: Some(sidechain),
+            detector: self.detector,
+            attack_coef: self.attack_coef,
+            release_coef: self.release_coef,
+            gain_reduction: self.gain_reduction,
+        }
+    }
+}
+
+impl<Side> Compressor<Side> {
+    /// The current gain reduction in dB (zero or negative).
+    pub fn gain_reduction(&self) -> f32 {
+        self.gain_reduction
+    }
+
+    /// The static gain reduction in dB for a signal at the given level.
+    fn target_gain_reduction(&self, level: f32) -> f32 {
+        let overshoot = level - self.threshold;
+        let slope = 1.0 / self.ratio - 1.0;
+        if 2.0 * overshoot <= -self.knee {
+            0.0
+        } else if 2.0 * overshoot.abs() < self.knee {
+            let knee_overshoot = overshoot + 0.5 * self.knee;
+            slope * knee_overshoot * knee_overshoot / (2.0 * self.knee)
+        } else {
+            slope * overshoot
+        }
+    }
+}
+
+impl<Side: SoundModule> SoundModule for Compressor<Side> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        if let Some(sidechain) = self.sidechain.as_mut() {
+            sidechain.set_sampling_parameters(params);
+        }
+        self.detector.set_sample_rate(params.sample_rate());
+        self.attack_coef = smoothing_coefficient(self.attack, params.sample_rate());
+        self.release_coef = smoothing_coefficient(self.release, params.sample_rate());
+    }
+
+    fn reset(&mut self) {
+        if let Some(sidechain) = self.sidechain.as_mut() {
+            sidechain.reset();
+        }
+        self.detector.reset();
+        self.gain_reduction = 0.0;
+    }
+}
+
+impl<Side> Filter for Compressor<Side> where
+    Side: SignalGenerator<Output=f32>
+{
+    type Input = f32;
+    type Output = f32;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let key = match self.sidechain.as_mut() {
+            Some(sidechain) => sidechain.next(),
+            None => input,
+        };
+        let level = self.detector.level_db(key);
+        let target = self.target_gain_reduction(level);
+        // more reduction is the attack phase, less reduction the release phase
+        let coef = if target < self.gain_reduction { self.attack_coef } else { self.release_coef };
+        self.gain_reduction = target + coef * (self.gain_reduction - target);
+        input * db_to_gain(self.gain_reduction + self.makeup_gain)
+    }
+}
+
+/// A noise gate muting the signal while its level is below the threshold.
+///
+/// The gate opens when the level rises above the threshold (in dB) and closes
+/// when it falls below the threshold minus the hysteresis, so that signals
+/// hovering around the threshold do not cause chatter. After the level fell
+/// below the closing threshold, the gate stays open for the hold time. While
+/// closed, the signal is attenuated by the range (in dB, negative).
+///
+/// With an expansion ratio, the gate acts as a downward expander instead: the
+/// attenuation of a closed gate grows by `ratio - 1` dB for every dB the
+/// level is below the threshold, limited by the range.
+///
+/// When a sidechain is set, its level controls the gate instead of the input.
+#[derive(Debug, Clone)]
+pub struct NoiseGate<Side> {
+    threshold: f32,
+    hysteresis: f32,
+    range: f32,
+    ratio: Option<f32>,
+    hold: Duration,
+    attack: Duration,
+    release: Duration,
+    sidechain: Option<Side>,
+    detector: LevelDetector,
+    hold_samples: usize,
+    attack_coef: f32,
+    release_coef: f32,
+    open: bool,
+    /// Samples remaining until the gate closes.
+    hold_remaining: usize,
+    /// The current linear gain.
+    gain: f32,
+}
+
+impl NoiseGate<f32> {
+    pub fn new(threshold: f32) -> Self {
+        NoiseGate {
+            threshold,
+            hysteresis: 0.0,
+            range: MIN_LEVEL_DB,
+            ratio: None,
+            hold: Duration::from_seconds(0.0),
+            attack: Duration::from_seconds(0.001),
+            release: Duration::from_seconds(0.05),
+            sidechain: None,
+            detector: LevelDetector::new(Detection::Peak),
+            hold_samples: 0,
+            attack_coef: 0.0,
+            release_coef: 0.0,
+            open: false,
+            hold_remaining: 0,
+            gain: 0.0,
+        }
+    }
+}
+
+impl<Side> NoiseGate<Side> where
+    Side: SignalGenerator<Output=f32>
+{
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis.max(0.0);
+        self
+    }
+
+    pub fn with_range(mut self, range: f32) -> Self {
+        self.range = range.min(0.0);
+        self
+    }
+
+    /// Turn the gate into a downward expander with the given ratio.
+    pub fn with_expansion(mut self, ratio: f32) -> Self {
+        self.ratio = Some(ratio.max(1.0));
+        self
+    }
+
+    pub fn with_hold(mut self, hold: Duration) -> Self {
+        self.hold = hold;
+        self
+    }
+
+    pub fn with_attack(mut self, attack: Duration) -> Self {
+        self.attack = attack;
+        self
+    }
+
+    pub fn with_release(mut self, release: Duration) -> Self {
+        self.release = release;
+        self
+    }
+
+    pub fn with_detection(mut self, detection: Detection) -> Self {
+        self.detector = LevelDetector::new(detection);
+        self
+    }
+
+    /// Control the gate by the level of the given signal instead of the input.
+    pub fn with_sidechain<S>(self, sidechain: S) -> NoiseGate<S> where
+        S: SignalGenerator<Output=f32>
+    {
+        NoiseGate {
+            threshold: self.threshold,
+            hysteresis: self.hysteresis,
+            range: self.range,
+            ratio: self.ratio,
+            hold: self.hold,
+            attack: self.attack,
+            release: self.release,
+            sidechain: Some(sidechain),
+            detector: self.detector,
+            hold_samples: self.hold_samples,
+            attack_coef: self.attack_coef,
+            release_coef: self.release_coef,
+            open: self.open,
+            hold_remaining: self.hold_remaining,
+            gain: self.gain,
+        }
+    }
+}
+
+impl<Side> NoiseGate<Side> {
+    /// Return true if the gate currently lets the signal pass.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Update the state of the gate and return the target gain in dB.
+    fn target_gain(&mut self, level: f32) -> f32 {
+        if level >= self.threshold {
+            self.open = true;
+            self.hold_remaining = self.hold_samples;
+        } else if self.open && level < self.threshold - self.hysteresis {
+            if self.hold_remaining == 0 {
+                self.open = false;
+            } else {
+                self.hold_remaining -= 1;
+            }
+        }
+
+        if self.open {
+            0.0
+        } else {
+            match self.ratio {
+                Some(ratio) => ((level - self.threshold) * (ratio - 1.0)).clamp(self.range, 0.0),
+                None => self.range,
+            }
+        }
+    }
+}
+
+impl<Side: SoundModule> SoundModule for NoiseGate<Side> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        if let Some(sidechain) = self.sidechain.as_mut() {
+            sidechain.set_sampling_parameters(params);
+        }
+        self.detector.set_sample_rate(params.sample_rate());
+        self.hold_samples = (params.sample_rate() * self.hold) as usize;
+        self.attack_coef = smoothing_coefficient(self.attack, params.sample_rate());
+        self.release_coef = smoothing_coefficient(self.release, params.sample_rate());
+    }
+
+    fn reset(&mut self) {
+        if let Some(sidechain) = self.sidechain.as_mut() {
+            sidechain.reset();
+        }
+        self.detector.reset();
+        self.open = false;
+        self.hold_remaining = 0;
+        self.gain = 0.0;
+    }
+}
+
+impl<Side> Filter for NoiseGate<Side> where
+    Side: SignalGenerator<Output=f32>
+{
+    type Input = f32;
+    type Output = f32;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let key = match self.sidechain.as_mut() {
+            Some(sidechain) => sidechain.next(),
+            None => input,
+        };
+        let level = self.detector.level_db(key);
+        let target = db_to_gain(self.target_gain(level));
+        let coef = if target > self.gain { self.attack_coef } else { self.release_coef };
+        self.gain = target + coef * (self.gain - target);
+        input * self.gain
+    }
+}
+
+#[test]
+fn test_compressor() {
+    let params = SamplingParameters::audio_cd();
+
+    // a 0 dB signal is compressed by 4:1 above -20 dB, resulting in -15 dB
+    let mut compressor = Compressor::new(-20.0, 4.0).with_makeup_gain(3.0);
+    compressor.set_sampling_parameters(&params);
+    let output = (0..44100).map(|_| compressor.filter(1.0)).last().unwrap();
+    assert!((gain_to_db(output) + 12.0).abs() < 1e-3);
+    assert!((compressor.gain_reduction() + 15.0).abs() < 1e-3);
+
+    // the soft knee reduces the gain slightly below the threshold
+    let soft = Compressor::new(-20.0, 4.0).with_knee(6.0);
+    assert!(soft.target_gain_reduction(-22.0) < 0.0);
+    assert_eq!(soft.target_gain_reduction(-23.5), 0.0);
+
+    // a loud sidechain ducks a quiet input, with the RMS level after the attack
+    let mut ducker = Compressor::new(-20.0, 10.0).with_detection(Detection::Rms).with_sidechain(1.0);
+    ducker.set_sampling_parameters(&params);
+    let output = (0..44100).map(|_| ducker.filter(0.05)).last().unwrap();
+    assert!((gain_to_db(output / 0.05) + 18.0).abs() < 1e-2);
+}
+
+#[test]
+fn test_noise_gate() {
+    let params = SamplingParameters::with_rate(Frequency::from_hertz(1000.0));
+    let mut gate = NoiseGate::new(-20.0).with_hysteresis(10.0).with_range(-60.0)
+        .with_hold(Duration::from_seconds(0.01)).with_attack(Duration::from_seconds(0.0));
+    gate.set_sampling_parameters(&params);
+
+    // closed at first, opens immediately without attack time
+    assert!(gate.filter(0.01) < 1e-4);
+    assert_eq!(gate.filter(0.5), 0.5);
+    // stays open within the hysteresis
+    let quiet = (0..100).map(|_| gate.filter(0.05)).last().unwrap();
+    assert!(gate.is_open() && quiet == 0.05);
+    // closes after the hold time and releases to the range
+    let hiss = (0..1000).map(|_| gate.filter(0.01)).collect::<Vec<f32>>();
+    assert_eq!(hiss[9], 0.01);
+    assert!(!gate.is_open() && (hiss[999] / 0.01 - db_to_gain(-60.0)).abs() < 1e-4);
+
+    // as an expander, the attenuation depends on the distance to the threshold
+    let mut expander = NoiseGate::new(-20.0).with_expansion(2.0).with_attack(Duration::from_seconds(0.0))
+        .with_release(Duration::from_seconds(0.0));
+    expander.set_sampling_parameters(&params);
+    assert!((expander.filter(0.01) / 0.01 - db_to_gain(-20.0)).abs() < 1e-4);
+
+    // a low sine just above the threshold keeps the gate open without hold
+    // time, and is not distorted by an expander
+    let params = SamplingParameters::audio_cd();
+    let sine = |i: usize| db_to_gain(-18.0) * (2.0 * std::f32::consts::PI * 30.0 * i as f32 / 44100.0).sin();
+    let mut gate = NoiseGate::new(-20.0);
+    gate.set_sampling_parameters(&params);
+    let mut expander = NoiseGate::new(-20.0).with_expansion(4.0);
+    expander.set_sampling_parameters(&params);
+    for i in 0..44100 {
+        gate.filter(sine(i));
+        let output = expander.filter(sine(i));
+        if i > 4410 {
+            assert!(gate.is_open() && expander.is_open());
+            assert!((output - sine(i)).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! A nonlinear model of the Moog transistor ladder filter, loosely following
+//! Antti Huovilainen's "Non-linear digital implementation of the Moog ladder
+//! filter".
+
+use foundation::{Filter, SignalGenerator, SoundModule, SamplingParameters, Frequency};
+use std;
+use std::sync::OnceLock;
+
+/// Highest cutoff frequency relative to the internal sample rate.
+const MAX_RELATIVE_CUTOFF: f32 = 0.45;
+/// Feedback gain at maximum resonance relative to the critical gain, so that
+/// the filter reliably self-oscillates despite the saturation.
+const MAX_FEEDBACK: f64 = 1.1;
+/// Number of intervals in the table of critical feedback gains.
+const FEEDBACK_TABLE_SIZE: usize = 512;
+
+/// A resonant 24 dB/octave low pass filter with saturation in every stage.
+///
+/// The resonance ranges from `0` to `1`, and the filter self-oscillates at the
+/// upper end of the range. Higher drive levels push the stages further into
+/// saturation. Optionally, the filter runs at twice the sample rate internally
+/// to reduce aliasing caused by the saturation.
+#[derive(Debug, Clone)]
+pub struct LadderFilter<Freq, Res> {
+    cutoff_frequency: Freq,
+    resonance: Res,
+    drive: f32,
+    oversampling: bool,
+    sample_rate: Frequency,
+    /// The cutoff frequency the current coefficient was computed for.
+    last_cutoff: f32,
+    /// The integration coefficient of each stage.
+    g: f32,
+    /// The feedback gain at maximum resonance.
+    max_feedback: f32,
+    /// The outputs of the four stages.
+    stages: [f32; 4],
+    /// The previous input, used for interpolating when oversampling.
+    last_input: f32,
+}
+
+impl<Freq, Res> LadderFilter<Freq, Res> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Res: SignalGenerator<Output=f32>
+{
+    pub fn new(cutoff_frequency: Freq, resonance: Res) -> Self {
+        LadderFilter {
+            cutoff_frequency,
+            resonance,
+            drive: 1.0,
+            oversampling: false,
+            sample_rate: Frequency::from_hertz(f32::NAN),
+            last_cutoff: f32::NAN,
+            g: 0.0,
+            max_feedback: 0.0,
+            stages: [0.0; 4],
+            last_input: 0.0,
+        }
+    }
+
+    /// Set the gain applied to the input before it enters the ladder.
+    pub fn with_drive(mut self, drive: f32) -> Self {
+        self.drive = drive;
+        self
+    }
+
+    /// Enable or disable 2x oversampling.
+    pub fn with_oversampling(mut self, oversampling: bool) -> Self {
+        self.oversampling = oversampling;
+        self.last_cutoff = f32::NAN;
+        self
+    }
+
+    fn internal_sample_rate(&self) -> f32 {
+        let factor = if self.oversampling { 2.0 } else { 1.0 };
+        self.sample_rate.to_hertz() * factor
+    }
+
+    fn update_coefficient(&mut self, cutoff: f32) {
+        if cutoff != self.last_cutoff {
+            self.last_cutoff = cutoff;
+            let relative_cutoff = (cutoff / self.internal_sample_rate()).clamp(0.0, MAX_RELATIVE_CUTOFF);
+            self.g = 1.0 - (-2.0 * std::f32::consts::PI * relative_cutoff).exp();
+            self.max_feedback = MAX_FEEDBACK as f32 * interpolate_critical_feedback(self.g);
+        }
+    }
+
+    /// Advance the ladder by one internal sample.
+    #[inline(always)]
+    fn tick(&mut self, input: f32, feedback: f32) -> f32 {
+        let mut stage_input = (self.drive * input - feedback * self.stages[3]).tanh();
+        for stage in self.stages.iter_mut() {
+            *stage += self.g * (stage_input - stage.tanh());
+            stage_input = stage.tanh();
+        }
+        self.stages[3]
+    }
+}
+
+impl<Freq, Res> SoundModule for LadderFilter<Freq, Res> where
+    Freq: SoundModule,
+    Res: SoundModule
+{
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.cutoff_frequency.set_sampling_parameters(params);
+        self.resonance.set_sampling_parameters(params);
+        self.sample_rate = params.sample_rate();
+        self.last_cutoff = f32::NAN;
+    }
+
+    fn reset(&mut self) {
+        self.cutoff_frequency.reset();
+        self.resonance.reset();
+        self.stages = [0.0; 4];
+        self.last_input = 0.0;
+    }
+}
+
+impl<Freq, Res> Filter for LadderFilter<Freq, Res> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Res: SignalGenerator<Output=f32>
+{
+    type Input = f32;
+    type Output = f32;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let cutoff = self.cutoff_frequency.next().to_hertz();
+        self.update_coefficient(cutoff);
+        let feedback = self.max_feedback * self.resonance.next().clamp(0.0, 1.0);
+
+        if self.oversampling {
+            let halfway = 0.5 * (self.last_input + input);
+            self.last_input = input;
+            let first = self.tick(halfway, feedback);
+            let second = self.tick(input, feedback);
+            0.5 * (first + second)
+        } else {
+            self.tick(input, feedback)
+        }
+    }
+}
+
+/// The largest integration coefficient, reached at the highest cutoff frequency.
+fn max_coefficient() -> f32 {
+    1.0 - (-2.0 * std::f32::consts::PI * MAX_RELATIVE_CUTOFF).exp()
+}
+
+/// Look up the critical feedback gain in a table that is computed once, since
+/// computing it directly is far too slow for a modulated cutoff frequency.
+fn interpolate_critical_feedback(g: f32) -> f32 {
+    static TABLE: OnceLock<Vec<f32>> = OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let step = max_coefficient() as f64 / FEEDBACK_TABLE_SIZE as f64;
+        // the gain approaches the analog value of 4 for low cutoff frequencies
+        std::iter::once(4.0)
+            .chain((1..=FEEDBACK_TABLE_SIZE).map(|index| critical_feedback(step * index as f64) as f32))
+            .collect()
+    });
+    let position = (g / max_coefficient()).clamp(0.0, 1.0) * FEEDBACK_TABLE_SIZE as f32;
+    let index = (position as usize).min(FEEDBACK_TABLE_SIZE - 1);
+    let fraction = position - index as f32;
+    table[index] + fraction * (table[index + 1] - table[index])
+}
+
+/// The feedback gain at which the linearized ladder starts to self-oscillate.
+///
+/// An analog ladder oscillates at a feedback gain of 4, but the unit delay in
+/// the digital feedback path shifts the phase, so that the oscillation occurs
+/// at a different frequency where the stages attenuate more. Candidate
+/// frequencies are those where the phase of the loop is -180 degrees, and the
+/// one requiring the least gain wins.
+fn critical_feedback(g: f64) -> f64 {
+    use std::f64::consts::PI;
+    const STEPS: usize = 256;
+
+    let pole = 1.0 - g;
+    // phase and magnitude response of a single stage `g / (1 - pole / z)`
+    let stage_phase = |w: f64| -(pole * w.sin()).atan2(1.0 - pole * w.cos());
+    let stage_gain = |w: f64| g / (1.0 - 2.0 * pole * w.cos() + pole * pole).sqrt();
+    let excess_phase = |w: f64| 4.0 * stage_phase(w) - w + PI;
+
+    // the loop phase is exactly -180 degrees at the Nyquist frequency
+    let mut best_gain = stage_gain(PI);
+    for step in 0..STEPS - 1 {
+        let (mut low, mut high) = (PI * step as f64 / STEPS as f64, PI * (step + 1) as f64 / STEPS as f64);
+        if excess_phase(low).signum() == excess_phase(high).signum() {
+            continue;
+        }
+        for _ in 0..40 {
+            let mid = 0.5 * (low + high);
+            if excess_phase(mid).signum() == excess_phase(low).signum() {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+        best_gain = best_gain.max(stage_gain(low));
+    }
+    1.0 / best_gain.powi(4)
+}
+
+#[test]
+fn test_ladder_filter() {
+    use foundation::generator::constant;
+
+    let params = SamplingParameters::audio_cd();
+    let cutoff = || constant(Frequency::from_hertz(1000.0));
+
+    // without resonance, small DC signals pass (almost) unchanged
+    for &oversampling in [false, true].iter() {
+        let mut ladder = LadderFilter::new(cutoff(), 0.0).with_oversampling(oversampling);
+        ladder.set_sampling_parameters(&params);
+        let dc = (0..10000).map(|_| ladder.filter(0.1)).last().unwrap();
+        assert!((dc - 0.1).abs() < 1e-4);
+    }
+
+    // at full resonance, an impulse keeps the filter ringing
+    let mut ladder = LadderFilter::new(cutoff(), 1.0).with_drive(2.0);
+    ladder.set_sampling_parameters(&params);
+    ladder.filter(1.0);
+    let peak = (0..44100).map(|_| ladder.filter(0.0)).skip(40000).fold(0.0f32, |a, b| a.max(b.abs()));
+    assert!(peak > 0.1 && peak <= 1.0);
+
+    // the table of feedback gains stays close to the exact values
+    for step in 0..1000 {
+        let g = max_coefficient() * step as f32 / 1000.0;
+        let exact = if step == 0 { 4.0 } else { critical_feedback(g as f64) as f32 };
+        assert!((interpolate_critical_feedback(g) - exact).abs() < 1e-2 * exact);
+    }
+}
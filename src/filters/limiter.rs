@@ -1,32 +1,146 @@
 use std;
-use foundation::{Sample, SamplingParameters, SoundModule, Filter};
+use foundation::{Duration, Sample, SamplingParameters, SoundModule, Filter};
 use data::SlidingWindowMax;
+use super::delay::RingBuffer;
+use super::dynamics::{db_to_gain, gain_to_db, smoothing_coefficient};
 
+/// Positions between two samples checked for inter-sample peaks.
+const TRUE_PEAK_POSITIONS: [f32; 3] = [0.25, 0.5, 0.75];
+
+/// A brickwall limiter keeping the signal below the ceiling (in dB) by
+/// looking ahead at the upcoming samples, at the cost of delaying the signal
+/// by the lookahead time.
+///
+/// The gain is lowered gradually during the lookahead time, so that it
+/// reaches the required reduction exactly when a peak arrives, and recovers
+/// exponentially within the release time afterwards. Optionally, peaks between
+/// samples (true peaks) are estimated by oversampling, so that the signal
+/// also stays below the ceiling after conversion to analog.
+#[derive(Debug, Clone)]
 pub struct LookaheadLimiter {
+    lookahead: Duration,
+    release: Duration,
+    ceiling: f32,
+    true_peak: bool,
+    /// The lookahead in samples.
     lookahead_count: usize,
-    buffer: SlidingWindowMax<Magnitude>
+    release_coef: f32,
+    /// The signal waiting to be output.
+    delay_buffer: RingBuffer<f32>,
+    /// The peaks of the samples within the lookahead window.
+    peaks: SlidingWindowMax<Magnitude>,
+    /// The last four input samples for estimating true peaks, newest last.
+    history: [f32; 4],
+    /// The gain after applying the release.
+    release_gain: f32,
+    /// The attenuations (one minus the gain) averaged for a smooth attack.
+    attenuations: RingBuffer<f32>,
+    attenuation_sum: f64,
+    /// The gain applied to the current output sample.
+    gain: f32,
 }
 
 impl LookaheadLimiter {
-    pub fn new(lookahead_count: usize) -> Self {
+    pub fn new(lookahead: Duration) -> Self {
         let mut limiter = LookaheadLimiter {
-            lookahead_count: lookahead_count,
-            buffer: SlidingWindowMax::with_capacity(lookahead_count)
+            lookahead,
+            release: Duration::from_seconds(0.1),
+            ceiling: 1.0,
+            true_peak: false,
+            lookahead_count: 1,
+            release_coef: 0.0,
+            delay_buffer: RingBuffer::new(1),
+            peaks: SlidingWindowMax::new(),
+            history: [0.0; 4],
+            release_gain: 1.0,
+            attenuations: RingBuffer::new(2),
+            attenuation_sum: 0.0,
+            gain: 1.0,
         };
         limiter.reset();
         limiter
     }
+
+    /// Set the highest level of the output in dB. A ceiling that is not a
+    /// number is taken as 0 dB.
+    pub fn with_ceiling(mut self, ceiling: f32) -> Self {
+        self.ceiling = if ceiling.is_nan() { 1.0 } else { db_to_gain(ceiling) };
+        self
+    }
+
+    pub fn with_release(mut self, release: Duration) -> Self {
+        self.release = release;
+        self
+    }
+
+    /// Enable or disable detection of peaks between samples.
+    pub fn with_true_peak(mut self, true_peak: bool) -> Self {
+        self.true_peak = true_peak;
+        self.reallocate_buffers();
+        self
+    }
+
+    /// The current gain reduction in dB (zero or negative).
+    pub fn gain_reduction(&self) -> f32 {
+        gain_to_db(self.gain)
+    }
+
+    /// The number of samples the signal is delayed by.
+    pub fn latency(&self) -> usize {
+        self.lookahead_count + self.detection_delay()
+    }
+
+    /// The number of samples it takes until a true peak is known, since the
+    /// way between two samples depends on their neighbours.
+    fn detection_delay(&self) -> usize {
+        if self.true_peak { 2 } else { 0 }
+    }
+
+    /// The number of samples the peaks are held for.
+    fn hold_count(&self) -> usize {
+        // a true peak affects both samples next to it
+        self.lookahead_count + 1 + self.detection_delay() / 2
+    }
+
+    fn reallocate_buffers(&mut self) {
+        self.delay_buffer.resize(self.latency());
+        self.attenuations.resize(self.lookahead_count + 1);
+        self.reset();
+    }
+
+    /// The peak of the newest sample when not detecting true peaks, otherwise
+    /// the peak of the way between the two previous samples.
+    fn detect_peak(&mut self, input: f32) -> f32 {
+        self.history = [self.history[1], self.history[2], self.history[3], input];
+        if !self.true_peak {
+            return input.abs();
+        }
+        let [p0, p1, p2, p3] = self.history;
+        let c1 = 0.5 * (p2 - p0);
+        let c2 = p0 - 2.5 * p1 + 2.0 * p2 - 0.5 * p3;
+        let c3 = 0.5 * (p3 - p0) + 1.5 * (p1 - p2);
+        TRUE_PEAK_POSITIONS.iter()
+            .map(|&t| (((c3 * t + c2) * t + c1) * t + p1).abs())
+            .fold(p2.abs(), f32::max)
+    }
 }
 
 impl SoundModule for LookaheadLimiter {
     fn reset(&mut self) {
-        self.buffer.clear();
-        std::iter::repeat(<Self as Filter>::Input::equilibrium())
-            .take(self.lookahead_count)
-            .for_each(|x| self.buffer.enqueue(Magnitude(x)));
+        self.delay_buffer.reset();
+        self.peaks.clear();
+        self.history = [0.0; 4];
+        self.release_gain = 1.0;
+        self.attenuations.reset();
+        self.attenuation_sum = 0.0;
+        self.gain = 1.0;
     }
 
-    fn set_sampling_parameters(&mut self, _params: &SamplingParameters) {}
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.lookahead_count = ((params.sample_rate() * self.lookahead).round() as usize).max(1);
+        self.release_coef = smoothing_coefficient(self.release, params.sample_rate());
+        self.reallocate_buffers();
+    }
 }
 
 impl Filter for LookaheadLimiter {
@@ -34,9 +148,33 @@ impl Filter for LookaheadLimiter {
     type Output = Self::Input;
 
     fn filter(&mut self, input: Self::Input) -> Self::Output {
-        let max_amplitude = self.buffer.maximum().map(|mag| mag.0.abs()).unwrap_or(1.0).max(1.0);
-        self.buffer.enqueue(Magnitude(input));
-        self.buffer.dequeue().unwrap().0 / max_amplitude
+        // hold the lowest required gain until the peak has been output
+        let peak = self.detect_peak(input);
+        self.peaks.enqueue(Magnitude(peak));
+        if self.peaks.len() > self.hold_count() {
+            self.peaks.dequeue();
+        }
+        let max_peak = self.peaks.maximum().map_or(0.0, |mag| mag.0.abs());
+        let held_gain = if max_peak > self.ceiling { self.ceiling / max_peak } else { 1.0 };
+
+        // reduce instantly, but recover exponentially
+        self.release_gain = if held_gain < self.release_gain {
+            held_gain
+        } else {
+            held_gain + self.release_coef * (self.release_gain - held_gain)
+        };
+
+        // averaging over the window turns the instant reduction into a ramp
+        // that arrives at the held gain when the peak leaves the delay buffer
+        let attenuation = 1.0 - self.release_gain;
+        let oldest = self.attenuations.shift(attenuation);
+        self.attenuation_sum += attenuation as f64 - oldest as f64;
+        let window = (self.lookahead_count + 1) as f64;
+        self.gain = (1.0 - self.attenuation_sum / window) as f32;
+
+        let output = self.delay_buffer.shift(input) * self.gain;
+        // guard against rounding errors
+        output.max(-self.ceiling).min(self.ceiling)
     }
 }
 
@@ -83,28 +221,55 @@ fn test_magnitude_order() {
     assert!(Magnitude(std::f32::NAN) < Magnitude(std::f32::NEG_INFINITY));
 }
 
-fn clamp<A>(value: A, min: A, max: A) -> A where
-    A: Copy + PartialOrd
-{
-    if value < min {
-        min
-    } else if value > max {
-        max
-    } else {
-        value
-    }
-}
-
 #[test]
-fn test_clamp() {
+fn test_hard_limit() {
     use std;
+    use foundation::Stereo;
 
-    assert_eq!(0.0, clamp(0.0, -1.0, 1.0));
-    assert_eq!(1.0, clamp(2.0, -1.0, 1.0));
-    assert_eq!(-1.0, clamp(-2.0, -1.0, 1.0));
-    assert!(clamp(std::f32::NAN, -1.0, 1.0).is_nan());
+    assert_eq!(0.0, hard_limit(0.0f32));
+    assert_eq!(1.0, hard_limit(2.0f32));
+    assert_eq!(-1.0, hard_limit(-2.0f32));
+    assert!(hard_limit(std::f32::NAN).is_nan());
+    assert_eq!(Stereo::stereo(1.0, -1.0), hard_limit(Stereo::stereo(2.0, -2.0)));
 }
 
 pub fn hard_limit<S: Sample>(input: S) -> S {
-    clamp(input, S::lower_limit(), S::upper_limit())
+    input.limit(S::lower_limit(), S::upper_limit())
+}
+
+#[test]
+fn test_lookahead_limiter() {
+    use foundation::Frequency;
+
+    let params = SamplingParameters::with_rate(Frequency::from_hertz(1000.0));
+    let mut limiter = LookaheadLimiter::new(Duration::from_seconds(0.01)).with_ceiling(-6.0)
+        .with_release(Duration::from_seconds(0.02));
+    limiter.set_sampling_parameters(&params);
+    assert_eq!(limiter.latency(), 10);
+
+    let input: Vec<f32> = (0..200).map(|i| if i == 50 { 4.0 } else { 0.25 }).collect();
+    let output: Vec<f32> = input.iter().map(|&x| limiter.filter(x)).collect();
+    // quiet signals pass unchanged with a delay
+    assert_eq!(&output[10..45], &input[..35]);
+    // the peak is reduced exactly to the ceiling, with a gradual attack
+    assert!((output[60] - db_to_gain(-6.0)).abs() < 1e-4);
+    assert!(output.iter().all(|&x| x <= db_to_gain(-6.0)));
+    assert!(output[51..60].windows(2).all(|w| w[1] < w[0]));
+    // and the gain recovers afterwards
+    assert!(limiter.gain_reduction() > -0.1);
+
+    // two equal samples can form an inter-sample peak above both of them
+    let burst = [0.0, 0.0, 0.98, 0.98, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+    let peak = |true_peak: bool| {
+        let mut limiter = LookaheadLimiter::new(Duration::from_seconds(0.002)).with_true_peak(true_peak);
+        limiter.set_sampling_parameters(&params);
+        burst.iter().map(|&x| limiter.filter(x)).fold(0.0f32, f32::max)
+    };
+    assert_eq!(peak(false), 0.98);
+    assert!(peak(true) < 0.9);
+
+    // a ceiling that is not a number limits to 0 dB
+    let mut limiter = LookaheadLimiter::new(Duration::from_seconds(0.002)).with_ceiling(f32::NAN);
+    limiter.set_sampling_parameters(&params);
+    assert!((0..20).map(|_| limiter.filter(2.0)).all(|x| x <= 1.0));
 }
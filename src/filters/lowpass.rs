@@ -1,4 +1,6 @@
 use foundation::{Filter, SignalGenerator, SoundModule, SamplingParameters, Frequency};
+use foundation::filter::process_buffered;
+use foundation::generator::BLOCK_SIZE;
 use std;
 
 #[derive(Debug, Clone)]
@@ -49,4 +51,29 @@ impl<Freq: SignalGenerator<Output=Frequency>> Filter for LowPassRC<Freq> {
         self.last_output = current;
         current
     }
+
+    fn process(&mut self, input: &[f32], output: &mut [f32]) {
+        debug_assert_eq!(input.len(), output.len());
+        let mut cutoffs = [Frequency::from_hertz(0.0); BLOCK_SIZE];
+        let mut alphas = [0.0; BLOCK_SIZE];
+        for (input, output) in input.chunks(BLOCK_SIZE).zip(output.chunks_mut(BLOCK_SIZE)) {
+            let cutoffs = &mut cutoffs[..input.len()];
+            self.cutoff_frequency.fill(cutoffs);
+            // the coefficients do not depend on each other and can be vectorized
+            for (alpha, &cutoff) in alphas.iter_mut().zip(cutoffs.iter()) {
+                let beta = 2.0 * std::f32::consts::PI * cutoff / self.sample_rate;
+                *alpha = beta / (beta + 1.0);
+            }
+            for ((output, &input), &alpha) in output.iter_mut().zip(input.iter()).zip(alphas.iter()) {
+                self.last_output = self.last_output * (1. - alpha) + input * alpha;
+                *output = self.last_output;
+            }
+        }
+    }
+
+    fn process_generated<G>(&mut self, generator: &mut G, output: &mut [f32]) where
+        G: SignalGenerator<Output=f32>
+    {
+        process_buffered(self, generator, output)
+    }
 }
@@ -1,22 +1,43 @@
-use foundation::{Filter, SoundModule, SignalGenerator, SamplingParameters};
+use foundation::{Duration, Filter, SoundModule, SignalGenerator, SamplingParameters};
 use foundation::filter;
 use knob::Knob;
 
+pub mod biquad;
+pub use self::biquad::{Biquad, BiquadType};
+
 pub mod delay;
 pub use self::delay::*;
 
 pub mod distortion;
 pub use self::distortion::*;
 
+pub mod dynamics;
+pub use self::dynamics::{Compressor, Detection, NoiseGate};
+
 pub mod inspection;
 pub use self::inspection::*;
 
+pub mod ladder;
+pub use self::ladder::LadderFilter;
+
 pub mod limiter;
 pub use self::limiter::*;
 
 pub mod lowpass;
 pub use self::lowpass::LowPassRC;
 
+pub mod modulation;
+pub use self::modulation::{Chorus, Flanger, Modulated, Phaser};
+
+pub mod reverb;
+pub use self::reverb::Reverb;
+
+pub mod stereo;
+pub use self::stereo::*;
+
+pub mod svf;
+pub use self::svf::{StateVariableFilter, SvfOutputs, SvfMode};
+
 /// Convenience trait for constructing a filtered signal generator. It is
 /// automatically implemented for all signal generators.
 pub trait FilteredExt: SignalGenerator {
@@ -46,10 +67,10 @@ pub trait FilteredExt: SignalGenerator {
     }
 
 
-    fn limit_with_lookahead(self, lookahead_count: usize) -> Filtered<Self, LookaheadLimiter> where
+    fn limit_with_lookahead(self, lookahead: Duration) -> Filtered<Self, LookaheadLimiter> where
         Self: SignalGenerator<Output = f32> + Sized
     {
-        self.filtered(LookaheadLimiter::new(lookahead_count))
+        self.filtered(LookaheadLimiter::new(lookahead))
     }
 }
 
@@ -84,6 +105,47 @@ impl<S, F> SignalGenerator for Filtered<S, F> where
     fn next(&mut self) -> Self::Output {
         self.filter.filter(self.generator.next())
     }
+
+    fn fill(&mut self, output: &mut [Self::Output]) {
+        self.filter.process_generated(&mut self.generator, output)
+    }
 }
 
 impl<S: SignalGenerator> FilteredExt for S {}
+
+#[test]
+fn test_block_processing() {
+    use foundation::generator::constant;
+    use foundation::types::units::{HZ, S};
+    use noise::white_noise;
+    use oscillator::{saw, sine};
+
+    let cutoff = sine(constant(3. * HZ)).map(|x| (x + 2.) * 100. * HZ);
+    let delay_time = sine(constant(5. * HZ)).map(|x| (x + 2.) * 0.01 * S);
+    let mut patch = saw(constant(110. * HZ)).add(white_noise().mul(0.1))
+        .filtered(LowPassRC::new(cutoff)
+            .chain(Delay::new(0.05 * S))
+            .chain(Echo::new(0.03 * S, 0.5))
+            .chain(ModulatedDelay::new(delay_time, 0.05 * S, Interpolation::Cubic).with_feedback(0.3)));
+    patch.set_sampling_parameters(&SamplingParameters::with_rate(1000. * HZ));
+
+    let expected: Vec<f32> = (0..1000).map(|_| patch.next()).collect();
+    // blocks of odd sizes, longer than the delays and the internal buffers
+    patch.reset();
+    let mut output = vec![0.0; 1000];
+    for chunk in output.chunks_mut(77) {
+        patch.fill(chunk);
+    }
+    assert_eq!(output, expected);
+
+    let mut noise = white_noise();
+    let expected: Vec<f32> = (0..1000).map(|_| noise.next()).collect();
+    noise.reset();
+    noise.fill(&mut output);
+    assert_eq!(output, expected);
+
+    // the samples passed between filters do not have to be `Copy`
+    let mut wrapped = white_noise().filtered(filter::lift(|x| vec![x]).chain(filter::lift(|x: Vec<f32>| x[0])));
+    wrapped.fill(&mut output);
+    assert!(output.iter().all(|x| x.abs() <= 1.0));
+}
@@ -0,0 +1,434 @@
+//! Modulation effects that change the delay or phase of a signal over time and
+//! mix the result with the original signal.
+//!
+//! All effects are driven by a low frequency oscillator producing values
+//! between `-1` and `1`, such as `oscillator::sine`. Depth, feedback and mix
+//! can be constants or arbitrary signals.
+
+use std;
+
+use foundation::{Duration, Filter, Frequency, SignalGenerator, SoundModule, SamplingParameters, Stereo};
+use oscillator::{sine, Oscillator};
+use waveform::Sine;
+use super::delay::{DelayLine, Interpolation};
+
+/// Feedback is limited to this magnitude to keep the effects stable.
+const MAX_FEEDBACK: f32 = 0.95;
+
+/// Delay of the first chorus voice.
+const CHORUS_DELAY: f32 = 0.012;
+/// Additional delay of each further chorus voice.
+const CHORUS_VOICE_SPACING: f32 = 0.004;
+/// Largest deviation from the nominal delay of a chorus voice at full depth.
+const CHORUS_SWING: f32 = 0.004;
+
+/// Shortest delay of the flanger.
+const FLANGER_DELAY: f32 = 0.0005;
+/// Delay range swept by the flanger at full depth.
+const FLANGER_SWING: f32 = 0.005;
+
+/// Lowest and highest break frequency of the phaser stages.
+const PHASER_RANGE: (f32, f32) = (100.0, 4000.0);
+
+/// A chorus with its LFO running at the given rate.
+pub fn chorus<Rate>(rate: Rate) -> Chorus<Oscillator<Sine, Rate>, f32, f32, f32> where
+    Rate: SignalGenerator<Output=Frequency>
+{
+    Chorus::new(sine(rate))
+}
+
+/// A flanger with its LFO running at the given rate.
+pub fn flanger<Rate>(rate: Rate) -> Flanger<Oscillator<Sine, Rate>, f32, f32, f32> where
+    Rate: SignalGenerator<Output=Frequency>
+{
+    Flanger::new(sine(rate))
+}
+
+/// A phaser with its LFO running at the given rate.
+pub fn phaser<Rate>(rate: Rate) -> Phaser<Oscillator<Sine, Rate>, f32, f32, f32> where
+    Rate: SignalGenerator<Output=Frequency>
+{
+    Phaser::new(sine(rate))
+}
+
+/// The signals controlling a modulation effect.
+#[derive(Debug, Clone)]
+struct Modulation<Lfo, Depth, Feedback, Mix> {
+    lfo: Lfo,
+    depth: Depth,
+    feedback: Feedback,
+    mix: Mix,
+}
+
+/// The current values of the control signals.
+struct Controls {
+    lfo: f32,
+    depth: f32,
+    feedback: f32,
+    mix: f32,
+}
+
+impl<Lfo, Depth, Feedback, Mix> Modulation<Lfo, Depth, Feedback, Mix> where
+    Lfo: SignalGenerator<Output=f32>,
+    Depth: SignalGenerator<Output=f32>,
+    Feedback: SignalGenerator<Output=f32>,
+    Mix: SignalGenerator<Output=f32>
+{
+    fn next(&mut self) -> Controls {
+        Controls {
+            lfo: self.lfo.next().clamp(-1.0, 1.0),
+            depth: self.depth.next().clamp(0.0, 1.0),
+            feedback: self.feedback.next().clamp(-MAX_FEEDBACK, MAX_FEEDBACK),
+            mix: self.mix.next().clamp(0.0, 1.0),
+        }
+    }
+}
+
+impl<Lfo, Depth, Feedback, Mix> SoundModule for Modulation<Lfo, Depth, Feedback, Mix> where
+    Lfo: SoundModule,
+    Depth: SoundModule,
+    Feedback: SoundModule,
+    Mix: SoundModule
+{
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.lfo.set_sampling_parameters(params);
+        self.depth.set_sampling_parameters(params);
+        self.feedback.set_sampling_parameters(params);
+        self.mix.set_sampling_parameters(params);
+    }
+
+    fn reset(&mut self) {
+        self.lfo.reset();
+        self.depth.reset();
+        self.feedback.reset();
+        self.mix.reset();
+    }
+}
+
+/// A modulation effect, consisting of the control signals shared by all
+/// effects and the state of the particular effect.
+#[derive(Debug, Clone)]
+pub struct Modulated<Effect, Lfo, Depth, Feedback, Mix> {
+    modulation: Modulation<Lfo, Depth, Feedback, Mix>,
+    effect: Effect,
+}
+
+impl<Effect, Lfo, Depth, Feedback, Mix> Modulated<Effect, Lfo, Depth, Feedback, Mix> where
+    Lfo: SignalGenerator<Output=f32>,
+    Depth: SignalGenerator<Output=f32>,
+    Feedback: SignalGenerator<Output=f32>,
+    Mix: SignalGenerator<Output=f32>
+{
+    /// Set how far the LFO sweeps the effect, from `0` to `1`.
+    pub fn with_depth<D>(self, depth: D) -> Modulated<Effect, Lfo, D, Feedback, Mix> where
+        D: SignalGenerator<Output=f32>
+    {
+        let Modulation { lfo, feedback, mix, .. } = self.modulation;
+        Modulated { modulation: Modulation { lfo, depth, feedback, mix }, effect: self.effect }
+    }
+
+    /// Set the fraction of the output fed back into the effect, limited to
+    /// keep it stable.
+    pub fn with_feedback<F>(self, feedback: F) -> Modulated<Effect, Lfo, Depth, F, Mix> where
+        F: SignalGenerator<Output=f32>
+    {
+        let Modulation { lfo, depth, mix, .. } = self.modulation;
+        Modulated { modulation: Modulation { lfo, depth, feedback, mix }, effect: self.effect }
+    }
+
+    /// Set the fraction of the effect in the output, from `0` (dry) to `1`
+    /// (wet).
+    pub fn with_mix<M>(self, mix: M) -> Modulated<Effect, Lfo, Depth, Feedback, M> where
+        M: SignalGenerator<Output=f32>
+    {
+        let Modulation { lfo, depth, feedback, .. } = self.modulation;
+        Modulated { modulation: Modulation { lfo, depth, feedback, mix }, effect: self.effect }
+    }
+}
+
+impl<Effect, Lfo, Depth, Feedback, Mix> SoundModule for Modulated<Effect, Lfo, Depth, Feedback, Mix> where
+    Effect: SoundModule,
+    Lfo: SoundModule,
+    Depth: SoundModule,
+    Feedback: SoundModule,
+    Mix: SoundModule
+{
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.modulation.set_sampling_parameters(params);
+        self.effect.set_sampling_parameters(params);
+    }
+
+    fn reset(&mut self) {
+        self.modulation.reset();
+        self.effect.reset();
+    }
+}
+
+/// A stereo chorus thickening the sound with several slightly detuned copies.
+///
+/// Each voice reads the delay line at its own nominal delay, which is swept
+/// by the LFO, every other voice in the opposite direction. The voices are
+/// spread across the stereo field, from all in the center (spread `0`) to
+/// the full width (spread `1`).
+pub type Chorus<Lfo, Depth, Feedback, Mix> = Modulated<ChorusEffect, Lfo, Depth, Feedback, Mix>;
+
+/// The state of a `Chorus`.
+#[derive(Debug, Clone)]
+pub struct ChorusEffect {
+    voices: usize,
+    spread: f32,
+    sample_rate: Frequency,
+    line: DelayLine,
+}
+
+impl<Lfo> Chorus<Lfo, f32, f32, f32> where
+    Lfo: SignalGenerator<Output=f32>
+{
+    pub fn new(lfo: Lfo) -> Self {
+        Modulated {
+            modulation: Modulation { lfo, depth: 0.5, feedback: 0.0, mix: 0.5 },
+            effect: ChorusEffect {
+                voices: 3,
+                spread: 1.0,
+                sample_rate: Frequency::from_hertz(f32::NAN),
+                line: DelayLine::new(Interpolation::Cubic),
+            },
+        }
+    }
+}
+
+impl<Lfo, Depth, Feedback, Mix> Chorus<Lfo, Depth, Feedback, Mix> where
+    Lfo: SignalGenerator<Output=f32>,
+    Depth: SignalGenerator<Output=f32>,
+    Feedback: SignalGenerator<Output=f32>,
+    Mix: SignalGenerator<Output=f32>
+{
+    pub fn with_voices(mut self, voices: usize) -> Self {
+        self.effect.voices = voices.max(1);
+        self
+    }
+
+    pub fn with_spread(mut self, spread: f32) -> Self {
+        self.effect.spread = spread.clamp(0.0, 1.0);
+        self
+    }
+}
+
+impl ChorusEffect {
+    fn max_delay(&self) -> Duration {
+        Duration::from_seconds(CHORUS_DELAY + CHORUS_VOICE_SPACING * (self.voices - 1) as f32 + CHORUS_SWING)
+    }
+}
+
+impl SoundModule for ChorusEffect {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.sample_rate = params.sample_rate();
+        self.line.resize(self.sample_rate * self.max_delay());
+    }
+
+    fn reset(&mut self) {
+        self.line.reset();
+    }
+}
+
+impl<Lfo, Depth, Feedback, Mix> Filter for Chorus<Lfo, Depth, Feedback, Mix> where
+    Lfo: SignalGenerator<Output=f32>,
+    Depth: SignalGenerator<Output=f32>,
+    Feedback: SignalGenerator<Output=f32>,
+    Mix: SignalGenerator<Output=f32>
+{
+    type Input = f32;
+    type Output = Stereo;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let controls = self.modulation.next();
+        let chorus = &mut self.effect;
+        let samples_per_second = chorus.sample_rate.to_hertz();
+        let swing = CHORUS_SWING * controls.depth * controls.lfo;
+
+        let mut wet = Stereo::splat(0.0);
+        let mut sum = 0.0;
+        for voice in 0..chorus.voices {
+            let direction = if voice % 2 == 0 { 1.0 } else { -1.0 };
+            let delay = CHORUS_DELAY + CHORUS_VOICE_SPACING * voice as f32 + direction * swing;
+            let output = chorus.line.read(delay * samples_per_second);
+            let position = if chorus.voices > 1 {
+                chorus.spread * (2.0 * voice as f32 / (chorus.voices - 1) as f32 - 1.0)
+            } else {
+                0.0
+            };
+            wet = wet + Stereo::stereo(output * (1.0 - position), output * (1.0 + position));
+            sum += output;
+        }
+        let scale = 1.0 / chorus.voices as f32;
+        chorus.line.write(input + controls.feedback * sum * scale);
+
+        Stereo::splat(input * (1.0 - controls.mix)) + wet * (scale * controls.mix)
+    }
+}
+
+/// A flanger mixing the signal with a copy whose very short delay is swept by
+/// the LFO, creating moving comb filter notches. Feedback emphasizes the
+/// resonances of the comb filter.
+pub type Flanger<Lfo, Depth, Feedback, Mix> = Modulated<FlangerEffect, Lfo, Depth, Feedback, Mix>;
+
+/// The state of a `Flanger`.
+#[derive(Debug, Clone)]
+pub struct FlangerEffect {
+    sample_rate: Frequency,
+    line: DelayLine,
+}
+
+impl<Lfo> Flanger<Lfo, f32, f32, f32> where
+    Lfo: SignalGenerator<Output=f32>
+{
+    pub fn new(lfo: Lfo) -> Self {
+        Modulated {
+            modulation: Modulation { lfo, depth: 0.5, feedback: 0.5, mix: 0.5 },
+            effect: FlangerEffect {
+                sample_rate: Frequency::from_hertz(f32::NAN),
+                line: DelayLine::new(Interpolation::Cubic),
+            },
+        }
+    }
+}
+
+impl SoundModule for FlangerEffect {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.sample_rate = params.sample_rate();
+        self.line.resize(self.sample_rate * Duration::from_seconds(FLANGER_DELAY + FLANGER_SWING));
+    }
+
+    fn reset(&mut self) {
+        self.line.reset();
+    }
+}
+
+impl<Lfo, Depth, Feedback, Mix> Filter for Flanger<Lfo, Depth, Feedback, Mix> where
+    Lfo: SignalGenerator<Output=f32>,
+    Depth: SignalGenerator<Output=f32>,
+    Feedback: SignalGenerator<Output=f32>,
+    Mix: SignalGenerator<Output=f32>
+{
+    type Input = f32;
+    type Output = f32;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let controls = self.modulation.next();
+        let flanger = &mut self.effect;
+        let delay = FLANGER_DELAY + FLANGER_SWING * controls.depth * 0.5 * (controls.lfo + 1.0);
+        let delayed = flanger.line.read(delay * flanger.sample_rate.to_hertz());
+        flanger.line.write(input + controls.feedback * delayed);
+        input * (1.0 - controls.mix) + delayed * controls.mix
+    }
+}
+
+/// A phaser mixing the signal with a copy passed through a cascade of first
+/// order allpass filters, whose break frequency is swept by the LFO. Every
+/// two stages create one notch in the spectrum.
+pub type Phaser<Lfo, Depth, Feedback, Mix> = Modulated<PhaserEffect, Lfo, Depth, Feedback, Mix>;
+
+/// The state of a `Phaser`.
+#[derive(Debug, Clone)]
+pub struct PhaserEffect {
+    sample_rate: Frequency,
+    /// The previous input and output of each stage.
+    stages: Vec<(f32, f32)>,
+    /// The previous output of the last stage.
+    last_output: f32,
+}
+
+impl<Lfo> Phaser<Lfo, f32, f32, f32> where
+    Lfo: SignalGenerator<Output=f32>
+{
+    pub fn new(lfo: Lfo) -> Self {
+        Modulated {
+            modulation: Modulation { lfo, depth: 0.5, feedback: 0.0, mix: 0.5 },
+            effect: PhaserEffect {
+                sample_rate: Frequency::from_hertz(f32::NAN),
+                stages: vec![(0.0, 0.0); 4],
+                last_output: 0.0,
+            },
+        }
+    }
+}
+
+impl<Lfo, Depth, Feedback, Mix> Phaser<Lfo, Depth, Feedback, Mix> where
+    Lfo: SignalGenerator<Output=f32>,
+    Depth: SignalGenerator<Output=f32>,
+    Feedback: SignalGenerator<Output=f32>,
+    Mix: SignalGenerator<Output=f32>
+{
+    pub fn with_stages(mut self, stages: usize) -> Self {
+        self.effect.stages = vec![(0.0, 0.0); stages.max(1)];
+        self
+    }
+}
+
+impl SoundModule for PhaserEffect {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.sample_rate = params.sample_rate();
+    }
+
+    fn reset(&mut self) {
+        self.stages.iter_mut().for_each(|stage| *stage = (0.0, 0.0));
+        self.last_output = 0.0;
+    }
+}
+
+impl<Lfo, Depth, Feedback, Mix> Filter for Phaser<Lfo, Depth, Feedback, Mix> where
+    Lfo: SignalGenerator<Output=f32>,
+    Depth: SignalGenerator<Output=f32>,
+    Feedback: SignalGenerator<Output=f32>,
+    Mix: SignalGenerator<Output=f32>
+{
+    type Input = f32;
+    type Output = f32;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let controls = self.modulation.next();
+        let phaser = &mut self.effect;
+        // sweep exponentially around the geometric center of the range
+        let (low, high) = PHASER_RANGE;
+        let frequency = low * (high / low).powf(0.5 + 0.5 * controls.depth * controls.lfo);
+        let t = (std::f32::consts::PI * (frequency / phaser.sample_rate.to_hertz()).min(0.49)).tan();
+        let a = (t - 1.0) / (t + 1.0);
+
+        let mut signal = input + controls.feedback * phaser.last_output;
+        for stage in phaser.stages.iter_mut() {
+            let output = a * signal + stage.0 - a * stage.1;
+            *stage = (signal, output);
+            signal = output;
+        }
+        phaser.last_output = signal;
+        input * (1.0 - controls.mix) + signal * controls.mix
+    }
+}
+
+#[test]
+fn test_modulation_effects() {
+    use foundation::generator::constant;
+
+    let params = SamplingParameters::audio_cd();
+    let impulse = |i| if i == 0 { 1.0 } else { 0.0 };
+
+    // without modulation, the flanger is a plain delay
+    let mut flanger = Flanger::new(0.0).with_depth(0.0).with_feedback(0.0).with_mix(1.0);
+    flanger.set_sampling_parameters(&params);
+    let output: Vec<f32> = (0..100).map(|i| flanger.filter(impulse(i))).collect();
+    let peak = output.iter().enumerate().fold((0, 0.0f32), |best, (i, &x)| if x > best.1 { (i, x) } else { best });
+    assert_eq!(peak.0, (FLANGER_DELAY * 44100.0).round() as usize);
+
+    // the allpass stages of the phaser preserve the energy of the signal
+    let mut phaser = phaser(constant(Frequency::from_hertz(0.5))).with_depth(0.0).with_mix(1.0).with_stages(6);
+    phaser.set_sampling_parameters(&params);
+    let energy: f32 = (0..44100).map(|i| phaser.filter(impulse(i)).powi(2)).sum();
+    assert!((energy - 1.0).abs() < 1e-3);
+
+    // the chorus voices are spread across the stereo field
+    let mut chorus = chorus(constant(Frequency::from_hertz(0.5))).with_mix(1.0).with_depth(1.0);
+    chorus.set_sampling_parameters(&params);
+    let output: Vec<Stereo> = (0..4410).map(|i| chorus.filter(impulse(i))).collect();
+    assert!(output.iter().all(|frame| frame.left().is_finite() && frame.right().is_finite()));
+    assert!(output.iter().any(|frame| (frame.left() - frame.right()).abs() > 0.1));
+}
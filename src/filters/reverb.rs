@@ -0,0 +1,226 @@
+//! An algorithmic reverb after Jezar's Freeverb, built from parallel comb
+//! filters followed by a chain of allpass filters for each channel.
+
+use foundation::{Duration, Filter, SoundModule, SamplingParameters, Stereo};
+use super::delay::{Delay, RingBuffer};
+
+/// Sample rate the delay line tunings are given for.
+const TUNING_SAMPLE_RATE: f32 = 44100.0;
+/// Delay line lengths of the comb filters of the left channel.
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+/// Delay line lengths of the allpass filters of the left channel.
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+/// Additional delay of the right channel, decorrelating both channels.
+const STEREO_SPREAD: usize = 23;
+/// Input gain keeping the sum of the comb filters in range.
+const INPUT_GAIN: f32 = 0.015;
+/// Feedback of the allpass filters.
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+/// A feedback comb filter with a one-pole low pass in the feedback path.
+#[derive(Debug, Clone)]
+struct Comb {
+    buffer: RingBuffer<f32>,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn new() -> Self {
+        Comb {
+            buffer: RingBuffer::new(1),
+            filter_store: 0.0,
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = *self.buffer.current_mut();
+        self.filter_store = output * (1.0 - damping) + self.filter_store * damping;
+        self.buffer.shift(input + self.filter_store * feedback);
+        output
+    }
+
+    fn reset(&mut self) {
+        self.buffer.reset();
+        self.filter_store = 0.0;
+    }
+}
+
+/// A Schroeder allpass filter.
+#[derive(Debug, Clone)]
+struct AllPass {
+    buffer: RingBuffer<f32>,
+}
+
+impl AllPass {
+    fn new() -> Self {
+        AllPass {
+            buffer: RingBuffer::new(1),
+        }
+    }
+
+    #[inline(always)]
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = *self.buffer.current_mut();
+        self.buffer.shift(input + delayed * ALLPASS_FEEDBACK);
+        delayed - input
+    }
+}
+
+/// The comb and allpass filters of one channel.
+#[derive(Debug, Clone)]
+struct Tank {
+    combs: Vec<Comb>,
+    allpasses: Vec<AllPass>,
+    /// Extra delay in samples at the tuning sample rate.
+    spread: usize,
+}
+
+impl Tank {
+    fn new(spread: usize) -> Self {
+        Tank {
+            combs: COMB_TUNING.iter().map(|_| Comb::new()).collect(),
+            allpasses: ALLPASS_TUNING.iter().map(|_| AllPass::new()).collect(),
+            spread,
+        }
+    }
+
+    fn resize(&mut self, scale: f32) {
+        let spread = self.spread;
+        let length = |tuning: usize| (((tuning + spread) as f32 * scale) as usize).max(1);
+        for (comb, &tuning) in self.combs.iter_mut().zip(COMB_TUNING.iter()) {
+            comb.buffer.resize(length(tuning));
+        }
+        for (allpass, &tuning) in self.allpasses.iter_mut().zip(ALLPASS_TUNING.iter()) {
+            allpass.buffer.resize(length(tuning));
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let combined = self.combs.iter_mut().map(|comb| comb.process(input, feedback, damping)).sum();
+        self.allpasses.iter_mut().fold(combined, |signal, allpass| allpass.process(signal))
+    }
+
+    fn reset(&mut self) {
+        self.combs.iter_mut().for_each(Comb::reset);
+        self.allpasses.iter_mut().for_each(|allpass| allpass.buffer.reset());
+    }
+}
+
+/// A stereo reverb producing only the reverberated (wet) signal, so it should
+/// usually be mixed with the input using `Filter::dry`.
+///
+/// - The room size (between `0` and `1`) determines the decay time.
+/// - The damping (between `0` and `1`) determines how fast high frequencies
+///   decay.
+/// - The width (between `0` and `1`) determines the stereo spread.
+/// - The pre-delay is the time before the reverberation starts.
+#[derive(Debug, Clone)]
+pub struct Reverb {
+    room_size: f32,
+    damping: f32,
+    width: f32,
+    pre_delay: Delay<f32>,
+    left: Tank,
+    right: Tank,
+}
+
+impl Reverb {
+    pub fn new() -> Self {
+        Reverb {
+            room_size: 0.5,
+            damping: 0.5,
+            width: 1.0,
+            pre_delay: Delay::new(Duration::from_seconds(0.0)),
+            left: Tank::new(0),
+            right: Tank::new(STEREO_SPREAD),
+        }
+    }
+
+    pub fn with_room_size(mut self, room_size: f32) -> Self {
+        self.room_size = room_size.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_damping(mut self, damping: f32) -> Self {
+        self.damping = damping.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_width(mut self, width: f32) -> Self {
+        self.width = width.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_pre_delay(mut self, pre_delay: Duration) -> Self {
+        self.pre_delay = Delay::new(pre_delay);
+        self
+    }
+
+    fn feedback(&self) -> f32 {
+        0.7 + 0.28 * self.room_size
+    }
+}
+
+impl Default for Reverb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SoundModule for Reverb {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.pre_delay.set_sampling_parameters(params);
+        let scale = params.sample_rate().to_hertz() / TUNING_SAMPLE_RATE;
+        self.left.resize(scale);
+        self.right.resize(scale);
+    }
+
+    fn reset(&mut self) {
+        self.pre_delay.reset();
+        self.left.reset();
+        self.right.reset();
+    }
+}
+
+impl Filter for Reverb {
+    type Input = f32;
+    type Output = Stereo;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let input = self.pre_delay.filter(input) * INPUT_GAIN;
+        let (feedback, damping) = (self.feedback(), 0.4 * self.damping);
+        let left = self.left.process(input, feedback, damping);
+        let right = self.right.process(input, feedback, damping);
+
+        let direct = 0.5 + 0.5 * self.width;
+        let crossed = 0.5 - 0.5 * self.width;
+        Stereo::stereo(left * direct + right * crossed, right * direct + left * crossed)
+    }
+}
+
+#[test]
+fn test_reverb_decays() {
+    let mut reverb = Reverb::new().with_room_size(0.8).with_pre_delay(Duration::from_seconds(0.01));
+    reverb.set_sampling_parameters(&SamplingParameters::audio_cd());
+
+    let output: Vec<Stereo> = (0..44100 * 4).map(|i| reverb.filter(if i == 0 { 1.0 } else { 0.0 })).collect();
+    // nothing before the pre-delay of 441 samples has passed, after that the
+    // same output as without a pre-delay, which still delays by one sample
+    let pre_delay = 441;
+    assert!(output[..pre_delay].iter().all(|frame| *frame == Stereo::stereo(0.0, 0.0)));
+    let mut undelayed = Reverb::new().with_room_size(0.8);
+    undelayed.set_sampling_parameters(&SamplingParameters::audio_cd());
+    for (i, frame) in output[pre_delay - 1..].iter().enumerate() {
+        assert_eq!(*frame, undelayed.filter(if i == 0 { 1.0 } else { 0.0 }));
+    }
+    let energy = |frames: &[Stereo]| frames.iter().map(|f| f.left().powi(2) + f.right().powi(2)).sum::<f32>();
+    let early = energy(&output[..44100]);
+    let late = energy(&output[44100 * 3..]);
+    assert!(early > 0.0 && late < early * 1e-3);
+
+    // the same reverb can be mixed with the dry signal
+    let mut mixed = Reverb::new().dry(0.5, 0.5);
+    mixed.set_sampling_parameters(&SamplingParameters::audio_cd());
+    assert_eq!(mixed.filter(1.0), Stereo::stereo(0.5, 0.5));
+}
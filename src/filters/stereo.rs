@@ -0,0 +1,115 @@
+//! A module for filters that produce or transform stereo signals.
+
+use std;
+
+use foundation::{Filter, SignalGenerator, SoundModule, SamplingParameters, Stereo};
+
+/// How the signal level is distributed between the channels when panning.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PanLaw {
+    /// The gains of both channels add up to one, causing a dip in perceived
+    /// loudness in the center.
+    Linear,
+    /// The powers of both channels add up to one, keeping the perceived
+    /// loudness constant (-3 dB in the center).
+    ConstantPower,
+}
+
+/// Places a mono signal in the stereo field. The position ranges from `-1`
+/// (hard left) to `1` (hard right).
+#[derive(Debug, Clone)]
+pub struct Pan<Pos> {
+    position: Pos,
+    law: PanLaw,
+}
+
+impl<Pos> Pan<Pos> where
+    Pos: SignalGenerator<Output=f32>
+{
+    pub fn new(position: Pos, law: PanLaw) -> Self {
+        Pan {
+            position,
+            law,
+        }
+    }
+}
+
+impl<Pos: SoundModule> SoundModule for Pan<Pos> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.position.set_sampling_parameters(params);
+    }
+
+    fn reset(&mut self) {
+        self.position.reset();
+    }
+}
+
+impl<Pos: SignalGenerator<Output=f32>> Filter for Pan<Pos> {
+    type Input = f32;
+    type Output = Stereo;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let position = self.position.next().clamp(-1.0, 1.0);
+        let (left, right) = match self.law {
+            PanLaw::Linear => ((1.0 - position) * 0.5, (1.0 + position) * 0.5),
+            PanLaw::ConstantPower => {
+                let angle = (position + 1.0) * std::f32::consts::FRAC_PI_4;
+                (angle.cos(), angle.sin())
+            }
+        };
+        Stereo::stereo(input * left, input * right)
+    }
+}
+
+/// Converts a left/right signal into a mid/side signal, where the mid channel
+/// is stored on the left and the side channel on the right.
+#[derive(Debug, Clone)]
+pub struct MidSideEncode;
+
+/// Converts a mid/side signal as produced by `MidSideEncode` back into a
+/// left/right signal.
+#[derive(Debug, Clone)]
+pub struct MidSideDecode;
+
+impl SoundModule for MidSideEncode {
+    fn set_sampling_parameters(&mut self, _params: &SamplingParameters) {}
+
+    fn reset(&mut self) {}
+}
+
+impl Filter for MidSideEncode {
+    type Input = Stereo;
+    type Output = Stereo;
+
+    #[inline(always)]
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        Stereo::stereo((input.left() + input.right()) * 0.5, (input.left() - input.right()) * 0.5)
+    }
+}
+
+impl SoundModule for MidSideDecode {
+    fn set_sampling_parameters(&mut self, _params: &SamplingParameters) {}
+
+    fn reset(&mut self) {}
+}
+
+impl Filter for MidSideDecode {
+    type Input = Stereo;
+    type Output = Stereo;
+
+    #[inline(always)]
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        Stereo::stereo(input.left() + input.right(), input.left() - input.right())
+    }
+}
+
+#[test]
+fn test_pan_and_mid_side() {
+    let mut center = Pan::new(0.0, PanLaw::ConstantPower);
+    let frame = center.filter(1.0);
+    assert!((frame.left().powi(2) + frame.right().powi(2) - 1.0).abs() < 1e-6);
+    assert_eq!(Pan::new(-1.0, PanLaw::Linear).filter(1.0), Stereo::stereo(1.0, 0.0));
+
+    let input = Stereo::stereo(0.75, -0.25);
+    assert_eq!(MidSideDecode.filter(MidSideEncode.filter(input)), input);
+}
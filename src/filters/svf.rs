@@ -0,0 +1,196 @@
+//! A topology-preserving state variable filter after Andrew Simper's "Linear
+//! Trapezoidal Integrated State Variable Filter".
+
+use foundation::{Filter, SignalGenerator, SoundModule, SamplingParameters, Frequency};
+use std;
+
+/// Smallest damping factor, keeping the filter just below self-oscillation.
+const MIN_DAMPING: f32 = 1e-3;
+/// Highest cutoff frequency relative to the sample rate.
+const MAX_RELATIVE_CUTOFF: f32 = 0.49;
+
+/// The simultaneous outputs of a state variable filter.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SvfOutputs {
+    pub low_pass: f32,
+    pub band_pass: f32,
+    pub high_pass: f32,
+    pub notch: f32,
+}
+
+/// Selects one of the outputs of a state variable filter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SvfMode {
+    LowPass,
+    BandPass,
+    HighPass,
+    Notch,
+}
+
+impl SvfOutputs {
+    pub fn select(&self, mode: SvfMode) -> f32 {
+        match mode {
+            SvfMode::LowPass => self.low_pass,
+            SvfMode::BandPass => self.band_pass,
+            SvfMode::HighPass => self.high_pass,
+            SvfMode::Notch => self.notch,
+        }
+    }
+}
+
+/// A resonant 12 dB/octave filter that stays stable when its cutoff frequency
+/// is modulated at audio rate. The resonance ranges from `0` (no resonance,
+/// Q = 0.5) to `1` (edge of self-oscillation).
+#[derive(Debug, Clone)]
+pub struct StateVariableFilter<Freq, Res> {
+    cutoff_frequency: Freq,
+    resonance: Res,
+    sample_rate: Frequency,
+    /// The parameters the current coefficients were computed for.
+    parameters: (f32, f32),
+    /// Damping factor `1 / Q`.
+    k: f32,
+    a1: f32,
+    a2: f32,
+    a3: f32,
+    /// Integrator states.
+    ic1eq: f32,
+    ic2eq: f32,
+}
+
+impl<Freq, Res> StateVariableFilter<Freq, Res> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Res: SignalGenerator<Output=f32>
+{
+    pub fn new(cutoff_frequency: Freq, resonance: Res) -> Self {
+        StateVariableFilter {
+            cutoff_frequency,
+            resonance,
+            sample_rate: Frequency::from_hertz(f32::NAN),
+            parameters: (f32::NAN, f32::NAN),
+            k: 2.0,
+            a1: 1.0,
+            a2: 0.0,
+            a3: 0.0,
+            ic1eq: 0.0,
+            ic2eq: 0.0,
+        }
+    }
+
+    /// Use only one of the outputs of the filter.
+    pub fn mode(self, mode: SvfMode) -> SvfSelect<Freq, Res> {
+        SvfSelect {
+            filter: self,
+            mode,
+        }
+    }
+
+    fn update_coefficients(&mut self) {
+        let parameters = (self.cutoff_frequency.next().to_hertz(), self.resonance.next());
+        if parameters != self.parameters {
+            self.parameters = parameters;
+            let relative_cutoff = (parameters.0 / self.sample_rate.to_hertz()).clamp(0.0, MAX_RELATIVE_CUTOFF);
+            let g = (std::f32::consts::PI * relative_cutoff).tan();
+            self.k = (2.0 - 2.0 * parameters.1.clamp(0.0, 1.0)).max(MIN_DAMPING);
+            self.a1 = 1.0 / (1.0 + g * (g + self.k));
+            self.a2 = g * self.a1;
+            self.a3 = g * self.a2;
+        }
+    }
+}
+
+impl<Freq, Res> SoundModule for StateVariableFilter<Freq, Res> where
+    Freq: SoundModule,
+    Res: SoundModule
+{
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.cutoff_frequency.set_sampling_parameters(params);
+        self.resonance.set_sampling_parameters(params);
+        self.sample_rate = params.sample_rate();
+        self.parameters = (f32::NAN, f32::NAN);
+    }
+
+    fn reset(&mut self) {
+        self.cutoff_frequency.reset();
+        self.resonance.reset();
+        self.ic1eq = 0.0;
+        self.ic2eq = 0.0;
+    }
+}
+
+impl<Freq, Res> Filter for StateVariableFilter<Freq, Res> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Res: SignalGenerator<Output=f32>
+{
+    type Input = f32;
+    type Output = SvfOutputs;
+
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        self.update_coefficients();
+        let v3 = input - self.ic2eq;
+        let v1 = self.a1 * self.ic1eq + self.a2 * v3;
+        let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
+        self.ic1eq = 2.0 * v1 - self.ic1eq;
+        self.ic2eq = 2.0 * v2 - self.ic2eq;
+
+        let high_pass = input - self.k * v1 - v2;
+        SvfOutputs {
+            low_pass: v2,
+            band_pass: v1,
+            high_pass,
+            notch: v2 + high_pass,
+        }
+    }
+}
+
+/// A state variable filter producing only one of its outputs.
+#[derive(Debug, Clone)]
+pub struct SvfSelect<Freq, Res> {
+    filter: StateVariableFilter<Freq, Res>,
+    mode: SvfMode,
+}
+
+impl<Freq, Res> SoundModule for SvfSelect<Freq, Res> where
+    Freq: SoundModule,
+    Res: SoundModule
+{
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.filter.set_sampling_parameters(params);
+    }
+
+    fn reset(&mut self) {
+        self.filter.reset();
+    }
+}
+
+impl<Freq, Res> Filter for SvfSelect<Freq, Res> where
+    Freq: SignalGenerator<Output=Frequency>,
+    Res: SignalGenerator<Output=f32>
+{
+    type Input = f32;
+    type Output = f32;
+
+    #[inline(always)]
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        self.filter.filter(input).select(self.mode)
+    }
+}
+
+#[test]
+fn test_svf_stability() {
+    use foundation::generator::constant;
+
+    let params = SamplingParameters::audio_cd();
+    let mut svf = StateVariableFilter::new(constant(Frequency::from_hertz(1000.0)), 0.5);
+    svf.set_sampling_parameters(&params);
+    let dc = (0..10000).map(|_| svf.filter(1.0)).last().unwrap();
+    assert!((dc.low_pass - 1.0).abs() < 1e-3);
+    assert!(dc.band_pass.abs() < 1e-3 && dc.high_pass.abs() < 1e-3);
+
+    // maximum resonance at and beyond the Nyquist frequency
+    let mut svf = StateVariableFilter::new(constant(params.nyquist_rate() * 1.5), 1.0).mode(SvfMode::LowPass);
+    svf.set_sampling_parameters(&params);
+    let peak = (0..10000).map(|i| svf.filter(if i % 2 == 0 { 1.0 } else { -1.0 }).abs())
+        .fold(0.0f32, f32::max);
+    assert!(peak.is_finite() && peak < 1e4);
+}
@@ -2,7 +2,8 @@ use std;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 
-use foundation::{SoundModule, SamplingParameters};
+use foundation::{SoundModule, SamplingParameters, Frame};
+use foundation::generator::{SignalGenerator, BLOCK_SIZE};
 
 /// The identity filter, returning a signal unchanged.
 #[derive(Debug, Clone)]
@@ -22,6 +23,14 @@ pub struct Split<F>(pub F);
 #[derive(Clone)]
 pub struct Map<Fun, I>(pub Fun, PhantomData<I>);
 
+/// Applies a separate instance of a mono filter to each channel of a frame.
+#[derive(Debug, Clone)]
+pub struct PerChannel<F, const N: usize>(pub [F; N]);
+
+/// A filter whose type has been erased, so that it can be chosen at runtime or
+/// stored alongside filters of different types.
+pub struct BoxedFilter<I, O>(pub Box<dyn Filter<Input=I, Output=O>>);
+
 /// A "dried" version of a filter that mixes the wet (filtered) signal with the
 /// dry (input) signal.
 #[derive(Debug, Clone)]
@@ -37,6 +46,22 @@ impl<Fun, I> Debug for Map<Fun, I> {
     }
 }
 
+/// Implements `Filter::process_generated` for filters with a block-based
+/// `process`, by generating the input in blocks and passing them to `process`.
+pub fn process_buffered<F, G>(filter: &mut F, generator: &mut G, output: &mut [F::Output]) where
+    F: Filter,
+    F::Input: Copy,
+    G: SignalGenerator<Output=F::Input>
+{
+    for output in output.chunks_mut(BLOCK_SIZE) {
+        // the intermediate buffer is initialized with the first sample
+        let mut buffer = [generator.next(); BLOCK_SIZE];
+        let buffer = &mut buffer[..output.len()];
+        generator.fill(&mut buffer[1..]);
+        filter.process(buffer, output);
+    }
+}
+
 /// Construct the identity filter.
 pub fn id<S>() -> Id<S> {
     Id(PhantomData)
@@ -49,12 +74,44 @@ pub fn lift<F, I, O>(fun: F) -> Map<F, I> where
     Map(fun, PhantomData)
 }
 
+/// Lift a mono filter to frames by using a copy of the filter for each channel.
+pub fn per_channel<F, const N: usize>(filter: F) -> PerChannel<F, N> where
+    F: Clone
+{
+    PerChannel(std::array::from_fn(|_| filter.clone()))
+}
+
 pub trait Filter: SoundModule {
     type Input;
     type Output;
 
     fn filter(&mut self, input: Self::Input) -> Self::Output;
 
+    /// Filter a whole block of samples at once. This is equivalent to calling
+    /// `filter` for each sample, but implementations may override it with a
+    /// faster version. Both slices must have the same length.
+    fn process(&mut self, input: &[Self::Input], output: &mut [Self::Output]) where
+        Self::Input: Copy
+    {
+        debug_assert_eq!(input.len(), output.len());
+        for (&input, output) in input.iter().zip(output.iter_mut()) {
+            *output = self.filter(input);
+        }
+    }
+
+    /// Filter a whole block of samples taken from a generator. Unlike `process`,
+    /// this does not require the input to be `Copy`, so that it can be used
+    /// for any filtered generator. Filters overriding `process` should forward
+    /// to it with `process_buffered`.
+    fn process_generated<G>(&mut self, generator: &mut G, output: &mut [Self::Output]) where
+        G: SignalGenerator<Output=Self::Input>,
+        Self: Sized
+    {
+        for output in output.iter_mut() {
+            *output = self.filter(generator.next());
+        }
+    }
+
     fn chain<F>(self, next: F) -> Chain<Self, F> where
         Self: Sized
     {
@@ -70,6 +127,13 @@ pub trait Filter: SoundModule {
             filter: self
         }
     }
+
+    /// Erase the type of the filter.
+    fn boxed(self) -> BoxedFilter<Self::Input, Self::Output> where
+        Self: Sized + 'static
+    {
+        BoxedFilter(Box::new(self))
+    }
 }
 
 impl<S> SoundModule for Id<S> {
@@ -114,6 +178,79 @@ impl<F1, F2> Filter for Chain<F1, F2> where
     fn filter(&mut self, input: Self::Input) -> Self::Output {
         self.1.filter(self.0.filter(input))
     }
+
+    fn process(&mut self, input: &[Self::Input], output: &mut [Self::Output]) where
+        Self::Input: Copy
+    {
+        debug_assert_eq!(input.len(), output.len());
+        self.process_generated(&mut SliceGenerator(input.iter()), output)
+    }
+
+    fn process_generated<G>(&mut self, generator: &mut G, output: &mut [Self::Output]) where
+        G: SignalGenerator<Output=Self::Input>
+    {
+        let mut first = Feed { filter: &mut self.0, generator };
+        self.1.process_generated(&mut first, output)
+    }
+}
+
+/// The samples of a slice as a generator, for filtering them block-wise.
+struct SliceGenerator<'a, T: 'a>(std::slice::Iter<'a, T>);
+
+impl<'a, T> SoundModule for SliceGenerator<'a, T> {
+    fn set_sampling_parameters(&mut self, _params: &SamplingParameters) {}
+
+    fn reset(&mut self) {}
+}
+
+impl<'a, T: Copy> SignalGenerator for SliceGenerator<'a, T> {
+    type Output = T;
+
+    #[inline(always)]
+    fn next(&mut self) -> T {
+        *self.0.next().expect("generated more samples than the slice holds")
+    }
+
+    fn fill(&mut self, output: &mut [T]) {
+        let (samples, rest) = self.0.as_slice().split_at(output.len());
+        output.copy_from_slice(samples);
+        self.0 = rest.iter();
+    }
+}
+
+/// A generator passed through a borrowed filter, so that the first filter of a
+/// chain can feed the second one block-wise.
+struct Feed<'a, F: 'a, G: 'a> {
+    filter: &'a mut F,
+    generator: &'a mut G,
+}
+
+impl<'a, F: SoundModule, G: SoundModule> SoundModule for Feed<'a, F, G> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.filter.set_sampling_parameters(params);
+        self.generator.set_sampling_parameters(params);
+    }
+
+    fn reset(&mut self) {
+        self.filter.reset();
+        self.generator.reset();
+    }
+}
+
+impl<'a, F, G> SignalGenerator for Feed<'a, F, G> where
+    F: Filter,
+    G: SignalGenerator<Output=F::Input>
+{
+    type Output = F::Output;
+
+    #[inline(always)]
+    fn next(&mut self) -> Self::Output {
+        self.filter.filter(self.generator.next())
+    }
+
+    fn fill(&mut self, output: &mut [Self::Output]) {
+        self.filter.process_generated(self.generator, output)
+    }
 }
 
 impl<F> SoundModule for Split<F> where
@@ -161,6 +298,38 @@ impl<F, I, O> Filter for Map<F, I> where
     }
 }
 
+impl<F, const N: usize> SoundModule for PerChannel<F, N> where
+    F: SoundModule
+{
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        for filter in self.0.iter_mut() {
+            filter.set_sampling_parameters(params);
+        }
+    }
+
+    fn reset(&mut self) {
+        for filter in self.0.iter_mut() {
+            filter.reset();
+        }
+    }
+}
+
+impl<F, const N: usize> Filter for PerChannel<F, N> where
+    F: Filter<Input=f32, Output=f32>
+{
+    type Input = Frame<N>;
+    type Output = Frame<N>;
+
+    #[inline(always)]
+    fn filter(&mut self, input: Self::Input) -> Self::Output {
+        let mut output = input;
+        for (sample, filter) in output.0.iter_mut().zip(self.0.iter_mut()) {
+            *sample = filter.filter(*sample);
+        }
+        output
+    }
+}
+
 impl<F> SoundModule for Dried<F> where
     F: SoundModule
 {
@@ -188,6 +357,42 @@ impl<F> Filter for Dried<F> where
     }
 }
 
+impl<I, O> Debug for BoxedFilter<I, O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("BoxedFilter").finish()
+    }
+}
+
+impl<I, O> SoundModule for BoxedFilter<I, O> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.0.set_sampling_parameters(params);
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+impl<I, O> Filter for BoxedFilter<I, O> {
+    type Input = I;
+    type Output = O;
+
+    #[inline(always)]
+    fn filter(&mut self, input: I) -> O {
+        self.0.filter(input)
+    }
+
+    fn process(&mut self, input: &[I], output: &mut [O]) where
+        I: Copy
+    {
+        self.0.process(input, output)
+    }
+
+    fn boxed(self) -> BoxedFilter<I, O> {
+        self
+    }
+}
+
 impl<'a, F> Filter for &'a mut F where
     F: 'a + Filter
 {
@@ -198,4 +403,18 @@ impl<'a, F> Filter for &'a mut F where
     fn filter(&mut self, input: Self::Input) -> Self::Output {
         (*self).filter(input)
     }
+
+    #[inline(always)]
+    fn process(&mut self, input: &[Self::Input], output: &mut [Self::Output]) where
+        Self::Input: Copy
+    {
+        (*self).process(input, output)
+    }
+
+    #[inline(always)]
+    fn process_generated<G>(&mut self, generator: &mut G, output: &mut [Self::Output]) where
+        G: SignalGenerator<Output=Self::Input>
+    {
+        (*self).process_generated(generator, output)
+    }
 }
@@ -0,0 +1,185 @@
+//! Multi-channel samples.
+
+use std;
+use std::ops::{Add, Sub, Mul, Div, Neg};
+
+use super::sample::{Sample, Interleaved};
+
+/// A frame holding one `f32` sample for each of `N` channels.
+///
+/// Frames are only partially ordered: a frame is smaller than another one if
+/// all of its channels are smaller or equal, and at least one is smaller.
+/// Limiting a frame therefore limits each channel separately.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frame<const N: usize>(pub [f32; N]);
+
+/// A frame with a left and a right channel.
+pub type Stereo = Frame<2>;
+
+impl<const N: usize> Frame<N> {
+    /// Construct a frame having the same value on all channels.
+    pub fn splat(value: f32) -> Self {
+        Frame([value; N])
+    }
+
+    /// Apply a function to each channel.
+    #[inline(always)]
+    pub fn map<F: Fn(f32) -> f32>(self, fun: F) -> Self {
+        Frame(self.0.map(fun))
+    }
+
+    /// Combine the corresponding channels of two frames.
+    #[inline(always)]
+    pub fn zip_with<F: Fn(f32, f32) -> f32>(self, other: Self, fun: F) -> Self {
+        Frame(std::array::from_fn(|i| fun(self.0[i], other.0[i])))
+    }
+}
+
+impl Frame<2> {
+    pub fn stereo(left: f32, right: f32) -> Self {
+        Frame([left, right])
+    }
+
+    pub fn left(&self) -> f32 {
+        self.0[0]
+    }
+
+    pub fn right(&self) -> f32 {
+        self.0[1]
+    }
+}
+
+impl<const N: usize> PartialOrd for Frame<N> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        use std::cmp::Ordering;
+        self.0.iter().zip(other.0.iter()).try_fold(Ordering::Equal, |ordering, (a, b)| {
+            match (ordering, a.partial_cmp(b)?) {
+                (ordering, Ordering::Equal) => Some(ordering),
+                (Ordering::Equal, channel) => Some(channel),
+                (ordering, channel) if ordering == channel => Some(ordering),
+                _ => None
+            }
+        })
+    }
+}
+
+impl<const N: usize> Sample for Frame<N> {
+    fn lower_limit() -> Self { Frame::splat(f32::lower_limit()) }
+    fn upper_limit() -> Self { Frame::splat(f32::upper_limit()) }
+    fn equilibrium() -> Self { Frame::splat(f32::equilibrium()) }
+
+    #[inline(always)]
+    fn limit(self, lower: Self, upper: Self) -> Self {
+        Frame(std::array::from_fn(|i| self.0[i].limit(lower.0[i], upper.0[i])))
+    }
+}
+
+impl<const N: usize> Interleaved for Frame<N> {
+    fn channels() -> u16 { N as u16 }
+
+    #[inline(always)]
+    fn channel(&self, index: usize) -> f32 {
+        self.0[index]
+    }
+
+    #[inline(always)]
+    fn from_channels<F: FnMut(usize) -> f32>(channel: F) -> Self {
+        Frame(std::array::from_fn(channel))
+    }
+}
+
+impl<const N: usize> Add<Frame<N>> for Frame<N> {
+    type Output = Frame<N>;
+
+    #[inline(always)]
+    fn add(self, other: Frame<N>) -> Frame<N> {
+        self.zip_with(other, |a, b| a + b)
+    }
+}
+
+/// Adding a mono sample to a frame adds it to all channels.
+impl<const N: usize> Add<Frame<N>> for f32 {
+    type Output = Frame<N>;
+
+    #[inline(always)]
+    fn add(self, other: Frame<N>) -> Frame<N> {
+        other.map(|a| self + a)
+    }
+}
+
+impl<const N: usize> Add<f32> for Frame<N> {
+    type Output = Frame<N>;
+
+    #[inline(always)]
+    fn add(self, other: f32) -> Frame<N> {
+        self.map(|a| a + other)
+    }
+}
+
+impl<const N: usize> Sub<Frame<N>> for Frame<N> {
+    type Output = Frame<N>;
+
+    #[inline(always)]
+    fn sub(self, other: Frame<N>) -> Frame<N> {
+        self.zip_with(other, |a, b| a - b)
+    }
+}
+
+impl<const N: usize> Neg for Frame<N> {
+    type Output = Frame<N>;
+
+    #[inline(always)]
+    fn neg(self) -> Frame<N> {
+        self.map(|a| -a)
+    }
+}
+
+/// Channel-wise multiplication, e.g. for applying a separate gain per channel.
+impl<const N: usize> Mul<Frame<N>> for Frame<N> {
+    type Output = Frame<N>;
+
+    #[inline(always)]
+    fn mul(self, other: Frame<N>) -> Frame<N> {
+        self.zip_with(other, |a, b| a * b)
+    }
+}
+
+impl<const N: usize> Mul<f32> for Frame<N> {
+    type Output = Frame<N>;
+
+    #[inline(always)]
+    fn mul(self, other: f32) -> Frame<N> {
+        self.map(|a| a * other)
+    }
+}
+
+impl<const N: usize> Mul<Frame<N>> for f32 {
+    type Output = Frame<N>;
+
+    #[inline(always)]
+    fn mul(self, other: Frame<N>) -> Frame<N> {
+        other.map(|a| self * a)
+    }
+}
+
+impl<const N: usize> Div<f32> for Frame<N> {
+    type Output = Frame<N>;
+
+    #[inline(always)]
+    fn div(self, other: f32) -> Frame<N> {
+        self.map(|a| a / other)
+    }
+}
+
+#[test]
+fn test_frame_order() {
+    assert!(Frame::stereo(0.0, 0.0) < Frame::stereo(0.0, 1.0));
+    assert!(Frame::stereo(1.0, 0.5) > Frame::stereo(0.0, 0.5));
+    assert!(Frame::stereo(0.0, 0.0) <= Frame::stereo(0.0, 0.0));
+    assert_eq!(Frame::stereo(0.0, 1.0).partial_cmp(&Frame::stereo(1.0, 0.0)), None);
+    assert_eq!(Frame::stereo(f32::NAN, 1.0).partial_cmp(&Frame::stereo(1.0, 1.0)), None);
+
+    // mixed frames are unordered, but are still limited per channel
+    assert_eq!(Frame::stereo(2.0, -2.0).limit(Frame::splat(-1.0), Frame::splat(1.0)), Frame::stereo(1.0, -1.0));
+    assert_eq!(Frame::stereo(0.5, -2.0).limit(Frame::splat(-1.0), Frame::splat(1.0)), Frame::stereo(0.5, -1.0));
+}
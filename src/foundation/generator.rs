@@ -10,6 +10,14 @@ pub struct Add<S1, S2>(pub S1, pub S2);
 #[derive(Debug, Clone)]
 pub struct Mul<S1, S2>(pub S1, pub S2);
 
+/// A signal generator whose type has been erased, so that it can be chosen at
+/// runtime or stored alongside generators of different types.
+pub struct BoxedGenerator<O>(pub Box<dyn SignalGenerator<Output=O>>);
+
+/// The number of samples processed at once by block-based implementations that
+/// need a temporary buffer.
+pub const BLOCK_SIZE: usize = 64;
+
 /// Construct constant-valued signal.
 pub fn constant<T>(value: T) -> Const<T> {
     Const(value)
@@ -20,6 +28,15 @@ pub trait SignalGenerator: SoundModule {
 
     fn next(&mut self) -> Self::Output;
 
+    /// Generate a whole block of samples at once. This is equivalent to calling
+    /// `next` for each sample, but implementations may override it with a
+    /// faster version.
+    fn fill(&mut self, output: &mut [Self::Output]) {
+        for sample in output.iter_mut() {
+            *sample = self.next();
+        }
+    }
+
     fn add<S>(self, other: S) -> Add<Self, S> where
         S: SignalGenerator,
         Self: Sized
@@ -33,6 +50,13 @@ pub trait SignalGenerator: SoundModule {
     {
         Mul(self, other)
     }
+
+    /// Erase the type of the generator.
+    fn boxed(self) -> BoxedGenerator<Self::Output> where
+        Self: Sized + 'static
+    {
+        BoxedGenerator(Box::new(self))
+    }
 }
 
 impl SignalGenerator for f32 {
@@ -42,6 +66,12 @@ impl SignalGenerator for f32 {
     fn next(&mut self) -> Self::Output {
         *self
     }
+
+    fn fill(&mut self, output: &mut [f32]) {
+        for sample in output.iter_mut() {
+            *sample = *self;
+        }
+    }
 }
 
 impl<T: Copy> SignalGenerator for Const<T> {
@@ -51,6 +81,12 @@ impl<T: Copy> SignalGenerator for Const<T> {
     fn next(&mut self) -> Self::Output {
         self.0
     }
+
+    fn fill(&mut self, output: &mut [T]) {
+        for sample in output.iter_mut() {
+            *sample = self.0;
+        }
+    }
 }
 
 /// Treating constant values as a sound module can be useful.
@@ -69,6 +105,11 @@ impl<'a, Signal> SignalGenerator for &'a mut Signal where
     fn next(&mut self) -> Self::Output {
         (*self).next()
     }
+
+    #[inline(always)]
+    fn fill(&mut self, output: &mut [Self::Output]) {
+        (*self).fill(output)
+    }
 }
 
 impl<S1: SoundModule, S2: SoundModule> SoundModule for Add<S1, S2> {
@@ -118,6 +159,39 @@ impl<S1: SignalGenerator, S2: SignalGenerator> SignalGenerator for Mul<S1, S2> w
     }
 }
 
+impl<O> std::fmt::Debug for BoxedGenerator<O> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("BoxedGenerator").finish()
+    }
+}
+
+impl<O> SoundModule for BoxedGenerator<O> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.0.set_sampling_parameters(params);
+    }
+
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+}
+
+impl<O> SignalGenerator for BoxedGenerator<O> {
+    type Output = O;
+
+    #[inline(always)]
+    fn next(&mut self) -> O {
+        self.0.next()
+    }
+
+    fn fill(&mut self, output: &mut [O]) {
+        self.0.fill(output)
+    }
+
+    fn boxed(self) -> BoxedGenerator<O> {
+        self
+    }
+}
+
 pub struct SignalIterator<Signal>(pub Signal);
 
 impl<Signal: SignalGenerator> Iterator for SignalIterator<Signal> {
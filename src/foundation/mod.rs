@@ -2,11 +2,13 @@
 pub mod module;
 pub mod types;
 pub mod sample;
+pub mod frame;
 pub mod generator;
 pub mod filter;
 
-pub use self::sample::{Sample, Resample};
+pub use self::sample::{Sample, Resample, Interleaved, I24};
+pub use self::frame::{Frame, Stereo};
 pub use self::module::{SoundModule, SamplingParameters};
-pub use self::generator::{SignalGenerator};
-pub use self::filter::Filter;
-pub use self::types::{Frequency, Duration, units};
+pub use self::generator::{SignalGenerator, BoxedGenerator};
+pub use self::filter::{Filter, BoxedFilter};
+pub use self::types::{Frequency, Duration, Pitch, Interval, units};
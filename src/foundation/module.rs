@@ -55,6 +55,16 @@ impl SoundModule for f32 {
 }
 
 
+impl<E: SoundModule + ?Sized> SoundModule for Box<E> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        (**self).set_sampling_parameters(params)
+    }
+
+    fn reset(&mut self) {
+        (**self).reset()
+    }
+}
+
 impl<'a, E> SoundModule for &'a mut E where
     E: 'a + SoundModule
 {
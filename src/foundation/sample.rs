@@ -4,6 +4,20 @@ pub trait Sample: Copy + PartialOrd {
     fn lower_limit() -> Self;
     fn upper_limit() -> Self;
     fn equilibrium() -> Self;
+
+    /// Restrict the sample to the range between `lower` and `upper`. Samples
+    /// with several channels must limit each channel on its own, since they
+    /// are only partially ordered.
+    #[inline(always)]
+    fn limit(self, lower: Self, upper: Self) -> Self {
+        if self < lower {
+            lower
+        } else if self > upper {
+            upper
+        } else {
+            self
+        }
+    }
 }
 
 impl Sample for f32 {
@@ -12,34 +26,181 @@ impl Sample for f32 {
     fn equilibrium() -> Self { 0.0 }
 }
 
+impl Sample for i8 {
+    fn lower_limit() -> Self { -i8::MAX }
+    fn upper_limit() -> Self { i8::MAX }
+    fn equilibrium() -> Self { 0 }
+}
+
 impl Sample for i16 {
     fn lower_limit() -> Self { -std::i16::MAX }
     fn upper_limit() -> Self { std::i16::MAX }
     fn equilibrium() -> Self { 0 }
 }
 
+/// A signed 24 bit integer sample, stored in the lower bits of an `i32`.
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+pub struct I24(i32);
+
+impl I24 {
+    pub const MAX: i32 = (1 << 23) - 1;
+
+    /// Construct a 24 bit sample from the lower 24 bits of `value`.
+    pub fn new(value: i32) -> Self {
+        I24((value << 8) >> 8)
+    }
+
+    pub fn to_i32(self) -> i32 {
+        self.0
+    }
+}
+
+impl Sample for I24 {
+    fn lower_limit() -> Self { I24(-I24::MAX) }
+    fn upper_limit() -> Self { I24(I24::MAX) }
+    fn equilibrium() -> Self { I24(0) }
+}
+
+impl Sample for i32 {
+    fn lower_limit() -> Self { -i32::MAX }
+    fn upper_limit() -> Self { i32::MAX }
+    fn equilibrium() -> Self { 0 }
+}
+
 pub trait Resample<To> {
     fn resample(self) -> To;
 }
 
+impl Resample<f32> for f32 {
+    fn resample(self) -> f32 {
+        self
+    }
+}
+
+impl Resample<i8> for f32 {
+    fn resample(self) -> i8 {
+        (self * i8::MAX as f32) as i8
+    }
+}
+
 impl Resample<i16> for f32 {
     fn resample(self) -> i16 {
         (self * std::i16::MAX as f32) as i16
     }
 }
 
+impl Resample<I24> for f32 {
+    fn resample(self) -> I24 {
+        I24((self * I24::MAX as f32) as i32)
+    }
+}
+
+impl Resample<i32> for f32 {
+    fn resample(self) -> i32 {
+        // f32 does not have enough precision to represent `i32::MAX` exactly
+        (self as f64 * i32::MAX as f64) as i32
+    }
+}
+
+impl Resample<f32> for i8 {
+    fn resample(self) -> f32 {
+        self as f32 / i8::MAX as f32
+    }
+}
+
+impl Resample<f32> for i16 {
+    fn resample(self) -> f32 {
+        self as f32 / i16::MAX as f32
+    }
+}
+
+impl Resample<f32> for I24 {
+    fn resample(self) -> f32 {
+        self.0 as f32 / I24::MAX as f32
+    }
+}
+
+impl Resample<f32> for i32 {
+    fn resample(self) -> f32 {
+        (self as f64 / i32::MAX as f64) as f32
+    }
+}
+
+/// A frame consisting of one sample per channel, such as a plain `f32` for mono
+/// signals or a pair of `f32`s for stereo signals.
+pub trait Interleaved {
+    /// The number of channels in each frame.
+    fn channels() -> u16;
+
+    /// Return the sample of the given channel.
+    fn channel(&self, index: usize) -> f32;
+
+    /// Construct a frame from a function returning the sample of each channel.
+    fn from_channels<F: FnMut(usize) -> f32>(channel: F) -> Self;
+}
+
+impl Interleaved for f32 {
+    fn channels() -> u16 { 1 }
+
+    #[inline(always)]
+    fn channel(&self, _index: usize) -> f32 {
+        *self
+    }
+
+    #[inline(always)]
+    fn from_channels<F: FnMut(usize) -> f32>(mut channel: F) -> Self {
+        channel(0)
+    }
+}
+
+/// Stereo frames, as produced e.g. by `filter::Split`.
+impl Interleaved for (f32, f32) {
+    fn channels() -> u16 { 2 }
+
+    #[inline(always)]
+    fn channel(&self, index: usize) -> f32 {
+        if index == 0 { self.0 } else { self.1 }
+    }
+
+    #[inline(always)]
+    fn from_channels<F: FnMut(usize) -> f32>(mut channel: F) -> Self {
+        (channel(0), channel(1))
+    }
+}
+
+impl<const N: usize> Interleaved for [f32; N] {
+    fn channels() -> u16 { N as u16 }
+
+    #[inline(always)]
+    fn channel(&self, index: usize) -> f32 {
+        self[index]
+    }
+
+    #[inline(always)]
+    fn from_channels<F: FnMut(usize) -> f32>(channel: F) -> Self {
+        std::array::from_fn(channel)
+    }
+}
 
 #[cfg(test)]
 macro_rules! test_resample_impl {
     ($from: ident, $to: ident) => ({
-        assert_eq!(<$from as Sample>::lower_limit().resample(), <$to as Sample>::lower_limit());
-        assert_eq!(<$from as Sample>::upper_limit().resample(), <$to as Sample>::upper_limit());
-        assert_eq!(<$from as Sample>::equilibrium().resample(), <$to as Sample>::equilibrium());
+        assert_eq!(<$from as Resample<$to>>::resample(<$from as Sample>::lower_limit()), <$to as Sample>::lower_limit());
+        assert_eq!(<$from as Resample<$to>>::resample(<$from as Sample>::upper_limit()), <$to as Sample>::upper_limit());
+        assert_eq!(<$from as Resample<$to>>::resample(<$from as Sample>::equilibrium()), <$to as Sample>::equilibrium());
     })
 }
 
 #[test]
 fn test_resample() {
-    test_resample_impl!(f32, i16)
+    test_resample_impl!(f32, f32);
+    test_resample_impl!(f32, i8);
+    test_resample_impl!(f32, i16);
+    test_resample_impl!(f32, I24);
+    test_resample_impl!(f32, i32);
+    test_resample_impl!(i8, f32);
+    test_resample_impl!(i16, f32);
+    test_resample_impl!(I24, f32);
+    test_resample_impl!(i32, f32);
 }
 
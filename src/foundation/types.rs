@@ -1,3 +1,4 @@
+use std;
 use std::ops::{Add, Sub, Mul, Div, Neg};
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
@@ -6,6 +7,15 @@ pub struct Frequency(f32);
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub struct Duration(f32);
 
+/// A musical pitch, measured in semitones like MIDI note numbers (middle C,
+/// `C4`, is 60), but not restricted to whole numbers.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Pitch(f32);
+
+/// The distance between two pitches, measured in semitones.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub struct Interval(f32);
+
 
 pub mod units {
     use super::{Frequency, Duration};
@@ -35,6 +45,185 @@ impl Duration {
     }
 }
 
+impl Pitch {
+    /// The MIDI note number of `A4`, the usual reference pitch.
+    pub const A4: Pitch = Pitch(69.);
+    /// The standard frequency of `A4`.
+    pub const CONCERT_PITCH: Frequency = Frequency(440.);
+
+    pub fn from_midi(note: u8) -> Self {
+        Pitch(note as f32)
+    }
+
+    pub fn from_semitones(semitones: f32) -> Self {
+        Pitch(semitones)
+    }
+
+    pub fn to_semitones(self) -> f32 {
+        self.0
+    }
+
+    /// The nearest MIDI note number.
+    pub fn midi_note(self) -> u8 {
+        self.0.round().clamp(0.0, 127.0) as u8
+    }
+
+    /// The frequency in twelve-tone equal temperament, where `A4` has the
+    /// given reference frequency.
+    pub fn to_frequency(self, reference: Frequency) -> Frequency {
+        reference * (self - Pitch::A4).ratio()
+    }
+
+    /// The pitch of a frequency in twelve-tone equal temperament, where `A4`
+    /// has the given reference frequency.
+    pub fn from_frequency(frequency: Frequency, reference: Frequency) -> Self {
+        Pitch::A4 + Interval::from_ratio(frequency / reference)
+    }
+}
+
+impl From<Pitch> for Frequency {
+    /// The frequency at concert pitch.
+    fn from(pitch: Pitch) -> Frequency {
+        pitch.to_frequency(Pitch::CONCERT_PITCH)
+    }
+}
+
+/// Names of the twelve pitch classes, starting at C.
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+/// Displays the name of the nearest note with its octave, e.g. `C#3`,
+/// followed by the deviation in cents if there is one, e.g. `A4+12c`.
+impl std::fmt::Display for Pitch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let nearest = self.0.round();
+        let (octave, pitch_class) = ((nearest as i32).div_euclid(12) - 1, (nearest as i32).rem_euclid(12));
+        write!(f, "{}{}", NOTE_NAMES[pitch_class as usize], octave)?;
+        let cents = ((self.0 - nearest) * 100.0).round();
+        if cents != 0.0 {
+            write!(f, "{:+}c", cents)?;
+        }
+        Ok(())
+    }
+}
+
+/// The error returned when parsing a pitch fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsePitchError;
+
+impl std::fmt::Display for ParsePitchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("invalid note name, expected e.g. \"A4\", \"C#3\" or \"Bb-1\"")
+    }
+}
+
+impl std::error::Error for ParsePitchError {}
+
+/// Parses a note name (`A` to `G`, case insensitive), any number of sharps
+/// (`#`) or flats (`b`), and the octave, e.g. `A4`, `C#3` or `Bb-1`. The
+/// note may be followed by a deviation in cents, e.g. `A4+12c`.
+impl std::str::FromStr for Pitch {
+    type Err = ParsePitchError;
+
+    fn from_str(text: &str) -> Result<Pitch, ParsePitchError> {
+        let mut chars = text.trim().chars();
+        let pitch_class = match chars.next().map(|c| c.to_ascii_uppercase()) {
+            Some('C') => 0,
+            Some('D') => 2,
+            Some('E') => 4,
+            Some('F') => 5,
+            Some('G') => 7,
+            Some('A') => 9,
+            Some('B') => 11,
+            _ => return Err(ParsePitchError),
+        };
+        let rest = chars.as_str();
+        let accidentals = rest.chars().take_while(|&c| c == '#' || c == 'b').count();
+        let offset = rest[..accidentals].chars().map(|c| if c == '#' { 1 } else { -1 }).sum::<i32>();
+        let rest = &rest[accidentals..];
+
+        // the first character may be the sign of the octave
+        let sign = rest.get(1..).and_then(|tail| tail.find(['+', '-'])).map(|index| index + 1);
+        let (octave, cents) = match sign {
+            Some(index) => {
+                let cents = rest[index..].strip_suffix('c').ok_or(ParsePitchError)?;
+                (&rest[..index], cents.parse::<f32>().map_err(|_| ParsePitchError)?)
+            },
+            None => (rest, 0.0),
+        };
+        if !cents.is_finite() {
+            return Err(ParsePitchError);
+        }
+        let semitones = octave.parse::<i32>().ok()
+            .and_then(|octave| octave.checked_add(1))
+            .and_then(|octave| octave.checked_mul(12))
+            .and_then(|semitones| semitones.checked_add(pitch_class + offset))
+            .ok_or(ParsePitchError)?;
+        Ok(Pitch(semitones as f32 + cents / 100.0))
+    }
+}
+
+impl Interval {
+    pub fn from_semitones(semitones: f32) -> Self {
+        Interval(semitones)
+    }
+
+    pub fn from_cents(cents: f32) -> Self {
+        Interval(cents / 100.0)
+    }
+
+    /// The interval between two frequencies with the given ratio.
+    pub fn from_ratio(ratio: f32) -> Self {
+        Interval(12.0 * ratio.log2())
+    }
+
+    pub fn to_semitones(self) -> f32 {
+        self.0
+    }
+
+    pub fn to_cents(self) -> f32 {
+        self.0 * 100.0
+    }
+
+    /// The frequency ratio of the interval in twelve-tone equal temperament.
+    pub fn ratio(self) -> f32 {
+        (self.0 / 12.0).exp2()
+    }
+}
+
+impl Frequency {
+    /// Shift the frequency by the given interval.
+    pub fn transpose(self, interval: Interval) -> Frequency {
+        self * interval.ratio()
+    }
+}
+
+impl Add<Interval> for Pitch {
+    type Output = Pitch;
+
+    #[inline(always)]
+    fn add(self, other: Interval) -> Pitch {
+        Pitch(self.0 + other.0)
+    }
+}
+
+impl Sub<Interval> for Pitch {
+    type Output = Pitch;
+
+    #[inline(always)]
+    fn sub(self, other: Interval) -> Pitch {
+        Pitch(self.0 - other.0)
+    }
+}
+
+impl Sub<Pitch> for Pitch {
+    type Output = Interval;
+
+    #[inline(always)]
+    fn sub(self, other: Pitch) -> Interval {
+        Interval(self.0 - other.0)
+    }
+}
+
 impl Div<Duration> for f32 {
     type Output = Frequency;
 
@@ -144,6 +333,33 @@ macro_rules! impl_scalar_mult {
 
 impl_additive!(Frequency);
 impl_additive!(Duration);
+impl_additive!(Interval);
 
 impl_scalar_mult!(Frequency);
 impl_scalar_mult!(Duration);
+impl_scalar_mult!(Interval);
+
+#[test]
+fn test_pitch() {
+    let parse = |name: &str| name.parse::<Pitch>();
+    assert_eq!(parse("A4"), Ok(Pitch::A4));
+    assert_eq!(parse("C#3").map(Pitch::midi_note), Ok(49));
+    assert_eq!(parse("Bb-1").map(Pitch::midi_note), Ok(10));
+    assert_eq!(parse("c4"), parse("B#3"));
+    assert_eq!(parse("A4-50c"), Ok(Pitch::A4 - Interval::from_cents(50.0)));
+    assert_eq!(parse("C-1-50c"), Ok(Pitch::from_semitones(-0.5)));
+    assert_eq!(parse("C-1+50c"), Ok(Pitch::from_semitones(0.5)));
+    assert_eq!(parse("H2"), Err(ParsePitchError));
+    assert_eq!(parse("A"), Err(ParsePitchError));
+    assert_eq!(parse("C2147483647"), Err(ParsePitchError));
+    assert_eq!(parse("C-178956972"), Err(ParsePitchError));
+    assert_eq!(parse("A4+infc"), Err(ParsePitchError));
+    assert_eq!(parse("A4-NaNc"), Err(ParsePitchError));
+
+    assert_eq!(Pitch::from_midi(61).to_string(), "C#4");
+    assert_eq!((Pitch::A4 + Interval::from_cents(12.0)).to_string(), "A4+12c");
+    assert_eq!(Frequency::from(Pitch::A4 + Interval::from_semitones(12.0)), Frequency(880.));
+    assert_eq!(Pitch::A4.to_frequency(Frequency(432.)), Frequency(432.));
+    assert_eq!(Pitch::from_frequency(Frequency(220.), Pitch::CONCERT_PITCH), Pitch::from_midi(57));
+    assert!((Frequency(100.).transpose(Interval::from_semitones(7.0)).to_hertz() - 149.83).abs() < 0.01);
+}
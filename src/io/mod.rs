@@ -0,0 +1,3 @@
+//! Getting audio into and out of the synthesizer.
+
+pub mod wav;
@@ -0,0 +1,604 @@
+//! Reading and writing RIFF/WAVE files.
+//!
+//! Only the uncompressed formats are supported, i.e. integer PCM and IEEE
+//! float samples.
+
+use std;
+use std::io::{Read, Write, Seek, SeekFrom};
+use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
+
+use foundation::{SignalGenerator, SamplingParameters, Duration, Sample, Resample, Interleaved, I24};
+use filters::hard_limit;
+
+/// `wFormatTag` of integer PCM data.
+const WAVE_FORMAT_PCM: u16 = 1;
+/// `wFormatTag` of IEEE floating point data.
+const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+/// `wFormatTag` of files whose actual format is stored in a sub-format GUID.
+const WAVE_FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+/// The encoding of the individual samples in a WAV file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SampleFormat {
+    /// Unsigned 8 bit samples, as mandated by the WAV format.
+    Int8,
+    Int16,
+    Int24,
+    Int32,
+    Float32,
+}
+
+impl SampleFormat {
+    /// The number of bits a single sample occupies in the file.
+    pub fn bits_per_sample(self) -> u16 {
+        match self {
+            SampleFormat::Int8 => 8,
+            SampleFormat::Int16 => 16,
+            SampleFormat::Int24 => 24,
+            SampleFormat::Int32 | SampleFormat::Float32 => 32,
+        }
+    }
+
+    /// The number of bytes a single sample occupies in the file.
+    pub fn bytes_per_sample(self) -> u16 {
+        self.bits_per_sample() / 8
+    }
+
+    /// Encode a single sample in the byte order of WAV files. Samples are
+    /// clamped to the range of the integer formats, floating point samples are
+    /// written as they are.
+    pub fn write_sample<W: Write>(self, sink: &mut W, sample: f32) -> std::io::Result<()> {
+        match self {
+            SampleFormat::Int8 => {
+                let value: i8 = hard_limit(sample).resample();
+                sink.write_u8((value as i16 + 128) as u8)
+            },
+            SampleFormat::Int16 =>
+                sink.write_i16::<LittleEndian>(hard_limit(sample).resample()),
+            SampleFormat::Int24 => {
+                let value: I24 = hard_limit(sample).resample();
+                sink.write_i24::<LittleEndian>(value.to_i32())
+            },
+            SampleFormat::Int32 =>
+                sink.write_i32::<LittleEndian>(hard_limit(sample).resample()),
+            SampleFormat::Float32 =>
+                sink.write_f32::<LittleEndian>(sample.resample()),
+        }
+    }
+
+    fn format_tag(self) -> u16 {
+        match self {
+            SampleFormat::Float32 => WAVE_FORMAT_IEEE_FLOAT,
+            _ => WAVE_FORMAT_PCM,
+        }
+    }
+
+    fn from_format_tag(format_tag: u16, bits_per_sample: u16) -> Option<Self> {
+        match (format_tag, bits_per_sample) {
+            (WAVE_FORMAT_PCM, 8) => Some(SampleFormat::Int8),
+            (WAVE_FORMAT_PCM, 16) => Some(SampleFormat::Int16),
+            (WAVE_FORMAT_PCM, 24) => Some(SampleFormat::Int24),
+            (WAVE_FORMAT_PCM, 32) => Some(SampleFormat::Int32),
+            (WAVE_FORMAT_IEEE_FLOAT, 32) => Some(SampleFormat::Float32),
+            _ => None
+        }
+    }
+}
+
+/// Describes the layout of the audio data in a WAV file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct WavSpec {
+    /// Number of interleaved channels.
+    pub channels: u16,
+    /// Number of frames per second.
+    pub sample_rate: u32,
+    /// Encoding of the samples.
+    pub format: SampleFormat,
+}
+
+impl WavSpec {
+    /// The number of bytes of a frame containing one sample of each channel.
+    /// A WAV file can only store frames of up to 65535 bytes.
+    pub fn block_align(&self) -> u32 {
+        self.channels as u32 * self.format.bytes_per_sample() as u32
+    }
+}
+
+/// Writes audio frames to a WAV file.
+///
+/// If the number of frames is known in advance, the file can be written to any
+/// sink (see `WavWriter::with_length`). Otherwise, the sink must be seekable so
+/// that the header can be patched once all frames have been written (see
+/// `WavWriter::finalize`).
+#[derive(Debug)]
+pub struct WavWriter<W: Write> {
+    sink: W,
+    spec: WavSpec,
+    /// Number of frames announced in the header, if known in advance.
+    announced_frames: Option<u32>,
+    /// Number of frames written so far.
+    written_frames: u32,
+}
+
+impl<W: Write> WavWriter<W> {
+    /// Start writing a WAV file of unknown length. The header sizes are only
+    /// valid after calling `finalize`.
+    pub fn new(sink: W, spec: WavSpec) -> std::io::Result<Self> {
+        let mut writer = Self::with_length(sink, spec, 0)?;
+        writer.announced_frames = None;
+        Ok(writer)
+    }
+
+    /// Start writing a WAV file containing exactly `num_frames` frames. The
+    /// header is valid right away, so this also works for sinks that cannot
+    /// seek, such as pipes.
+    pub fn with_length(mut sink: W, spec: WavSpec, num_frames: u32) -> std::io::Result<Self> {
+        if spec.channels == 0 {
+            return Err(invalid_input("WAV files need at least one channel"));
+        }
+        frame_sizes(&spec).ok_or_else(|| invalid_input("too many channels for a WAV file"))?;
+        data_size(&spec, num_frames)?;
+        write_header(&mut sink, &spec, num_frames)?;
+        Ok(WavWriter {
+            sink,
+            spec,
+            announced_frames: Some(num_frames),
+            written_frames: 0,
+        })
+    }
+
+    pub fn spec(&self) -> &WavSpec {
+        &self.spec
+    }
+
+    /// The number of frames that have been written so far.
+    pub fn len(&self) -> u32 {
+        self.written_frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.written_frames == 0
+    }
+
+    /// Write a single frame. Samples are clamped to the range of the integer
+    /// formats, floating point samples are written as they are.
+    pub fn write_frame<F: Interleaved>(&mut self, frame: &F) -> std::io::Result<()> {
+        if F::channels() != self.spec.channels {
+            return Err(invalid_input("frame does not match the number of channels of the WAV file"));
+        }
+        self.check_length(1)?;
+        for index in 0..self.spec.channels as usize {
+            self.write_sample(frame.channel(index))?;
+        }
+        self.written_frames += 1;
+        Ok(())
+    }
+
+    /// Write interleaved samples, whose number must be a multiple of the
+    /// number of channels. This is useful when the number of channels is only
+    /// known at runtime.
+    pub fn write_samples(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        let channels = self.spec.channels as usize;
+        if !samples.len().is_multiple_of(channels) {
+            return Err(invalid_input("samples do not form whole frames"));
+        }
+        let num_frames = (samples.len() / channels) as u32;
+        self.check_length(num_frames)?;
+        for &sample in samples {
+            self.write_sample(sample)?;
+        }
+        self.written_frames += num_frames;
+        Ok(())
+    }
+
+    fn write_sample(&mut self, sample: f32) -> std::io::Result<()> {
+        self.spec.format.write_sample(&mut self.sink, sample)
+    }
+
+    /// Check that another `num_frames` frames fit into the file.
+    fn check_length(&self, num_frames: u32) -> std::io::Result<()> {
+        let total_frames = self.written_frames.checked_add(num_frames)
+            .ok_or_else(|| invalid_input("too many frames for a WAV file"))?;
+        match self.announced_frames {
+            Some(announced_frames) if total_frames > announced_frames =>
+                Err(invalid_input("more frames than announced in the WAV header")),
+            _ => data_size(&self.spec, total_frames).map(|_| ()),
+        }
+    }
+
+    /// Write the pad byte following a `data` chunk of odd size.
+    fn write_padding(&mut self) -> std::io::Result<()> {
+        if data_size(&self.spec, self.written_frames)? & 1 == 1 {
+            self.sink.write_u8(0)?;
+        }
+        Ok(())
+    }
+
+    /// Finish a file whose length was announced in advance. Missing frames are
+    /// filled with silence. Files of unknown length must be finished with
+    /// `finalize` instead.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        let announced_frames = self.announced_frames
+            .ok_or_else(|| invalid_input("the length of the WAV file was not announced, use `finalize`"))?;
+        while self.written_frames < announced_frames {
+            let silence = vec![<f32 as Sample>::equilibrium(); self.spec.channels as usize];
+            for sample in silence {
+                self.write_sample(sample)?;
+            }
+            self.written_frames += 1;
+        }
+        self.write_padding()?;
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    /// Finish the file by patching the header with the number of frames that
+    /// were actually written.
+    pub fn finalize(mut self) -> std::io::Result<W> {
+        let data_end = self.sink.stream_position()?;
+        let start = data_end - header_size(&self.spec) - data_size(&self.spec, self.written_frames)? as u64;
+        self.write_padding()?;
+        let end = self.sink.stream_position()?;
+        self.sink.seek(SeekFrom::Start(start))?;
+        write_header(&mut self.sink, &self.spec, self.written_frames)?;
+        self.sink.seek(SeekFrom::Start(end))?;
+        self.sink.flush()?;
+        Ok(self.sink)
+    }
+}
+
+/// Render `duration` worth of frames from the generator into a WAV file.
+///
+/// The sampling parameters are applied to the generator before rendering.
+pub fn render<S, W>(generator: &mut S, params: &SamplingParameters, duration: Duration,
+                    format: SampleFormat, sink: W) -> std::io::Result<W> where
+    S: SignalGenerator,
+    S::Output: Interleaved,
+    W: Write
+{
+    let num_frames = (params.sample_rate() * duration).max(0.0).round();
+    if num_frames > u32::MAX as f32 {
+        return Err(invalid_input("duration too long for a WAV file"));
+    }
+    let spec = WavSpec {
+        channels: S::Output::channels(),
+        sample_rate: params.sample_rate().to_hertz().round() as u32,
+        format,
+    };
+
+    generator.set_sampling_parameters(params);
+    let mut writer = WavWriter::with_length(sink, spec, num_frames as u32)?;
+    for _ in 0..num_frames as u32 {
+        writer.write_frame(&generator.next())?;
+    }
+    writer.finish()
+}
+
+/// Reads audio frames from a WAV file.
+#[derive(Debug)]
+pub struct WavReader<R: Read> {
+    source: R,
+    spec: WavSpec,
+    /// Number of frames in the file.
+    num_frames: u32,
+    /// Number of frames that have not been read yet.
+    remaining_frames: u32,
+}
+
+impl<R: Read> WavReader<R> {
+    /// Parse the header of a WAV file, leaving the source positioned at the
+    /// start of the sample data.
+    pub fn new(mut source: R) -> std::io::Result<Self> {
+        if &read_tag(&mut source)? != b"RIFF" {
+            return Err(invalid_data("not a RIFF file"));
+        }
+        source.read_u32::<LittleEndian>()?;
+        if &read_tag(&mut source)? != b"WAVE" {
+            return Err(invalid_data("not a WAVE file"));
+        }
+
+        let mut spec = None;
+        loop {
+            let chunk_id = read_tag(&mut source)?;
+            let chunk_size = source.read_u32::<LittleEndian>()?;
+            match &chunk_id {
+                b"fmt " => spec = Some(read_format_chunk(&mut source, chunk_size)?),
+                b"data" => {
+                    let spec = spec.ok_or_else(|| invalid_data("data chunk before fmt chunk"))?;
+                    let num_frames = chunk_size / spec.block_align();
+                    return Ok(WavReader {
+                        source,
+                        spec,
+                        num_frames,
+                        remaining_frames: num_frames,
+                    })
+                },
+                // chunks are padded to an even number of bytes
+                _ => skip(&mut source, chunk_size as u64 + (chunk_size & 1) as u64)?,
+            }
+        }
+    }
+
+    pub fn spec(&self) -> &WavSpec {
+        &self.spec
+    }
+
+    /// The total number of frames in the file.
+    pub fn len(&self) -> u32 {
+        self.num_frames
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_frames == 0
+    }
+
+    /// Read the next frame, or `None` when all frames have been read.
+    pub fn read_frame<F: Interleaved>(&mut self) -> std::io::Result<Option<F>> {
+        if F::channels() != self.spec.channels {
+            return Err(invalid_input("frame does not match the number of channels of the WAV file"));
+        }
+        if self.remaining_frames == 0 {
+            return Ok(None);
+        }
+        let mut samples = [0.0f32; 8];
+        let mut frame = Vec::new();
+        let channels: &mut [f32] = if self.spec.channels as usize <= samples.len() {
+            &mut samples[..self.spec.channels as usize]
+        } else {
+            frame.resize(self.spec.channels as usize, 0.0);
+            &mut frame
+        };
+        for sample in channels.iter_mut() {
+            *sample = self.read_sample()?;
+        }
+        self.remaining_frames -= 1;
+        Ok(Some(F::from_channels(|index| channels[index])))
+    }
+
+    /// Read all remaining frames, returning the interleaved samples of all
+    /// channels.
+    pub fn into_samples(mut self) -> std::io::Result<Vec<f32>> {
+        let num_samples = self.remaining_frames as usize * self.spec.channels as usize;
+        let mut samples = Vec::with_capacity(num_samples);
+        for _ in 0..num_samples {
+            samples.push(self.read_sample()?);
+        }
+        Ok(samples)
+    }
+
+    fn read_sample(&mut self) -> std::io::Result<f32> {
+        Ok(match self.spec.format {
+            SampleFormat::Int8 => ((self.source.read_u8()? as i16 - 128) as i8).resample(),
+            SampleFormat::Int16 => self.source.read_i16::<LittleEndian>()?.resample(),
+            SampleFormat::Int24 => I24::new(self.source.read_i24::<LittleEndian>()?).resample(),
+            SampleFormat::Int32 => self.source.read_i32::<LittleEndian>()?.resample(),
+            SampleFormat::Float32 => self.source.read_f32::<LittleEndian>()?,
+        })
+    }
+}
+
+fn read_tag<R: Read>(source: &mut R) -> std::io::Result<[u8; 4]> {
+    let mut tag = [0u8; 4];
+    source.read_exact(&mut tag)?;
+    Ok(tag)
+}
+
+fn skip<R: Read>(source: &mut R, num_bytes: u64) -> std::io::Result<()> {
+    let skipped = std::io::copy(&mut source.take(num_bytes), &mut std::io::sink())?;
+    if skipped < num_bytes {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "truncated chunk"));
+    }
+    Ok(())
+}
+
+fn read_format_chunk<R: Read>(source: &mut R, chunk_size: u32) -> std::io::Result<WavSpec> {
+    if chunk_size < 16 {
+        return Err(invalid_data("fmt chunk too short"));
+    }
+    let mut format_tag = source.read_u16::<LittleEndian>()?;
+    let channels = source.read_u16::<LittleEndian>()?;
+    let sample_rate = source.read_u32::<LittleEndian>()?;
+    let _byte_rate = source.read_u32::<LittleEndian>()?;
+    let block_align = source.read_u16::<LittleEndian>()?;
+    let bits_per_sample = source.read_u16::<LittleEndian>()?;
+    let mut remaining = chunk_size as u64 - 16;
+
+    if format_tag == WAVE_FORMAT_EXTENSIBLE {
+        if chunk_size < 40 {
+            return Err(invalid_data("fmt chunk too short for WAVE_FORMAT_EXTENSIBLE"));
+        }
+        // `cbSize`, `wValidBitsPerSample` and `dwChannelMask`, followed by the
+        // sub-format GUID whose first two bytes are the actual format tag
+        skip(source, 8)?;
+        format_tag = source.read_u16::<LittleEndian>()?;
+        remaining -= 10;
+    }
+    skip(source, remaining + (chunk_size & 1) as u64)?;
+
+    if channels == 0 {
+        return Err(invalid_data("WAV file without channels"));
+    }
+    let format = SampleFormat::from_format_tag(format_tag, bits_per_sample)
+        .ok_or_else(|| invalid_data("unsupported sample format"))?;
+    let spec = WavSpec {
+        channels,
+        sample_rate,
+        format,
+    };
+    match frame_sizes(&spec) {
+        Some((expected, _)) if expected == block_align => Ok(spec),
+        Some(_) => Err(invalid_data("block alignment does not match the sample format")),
+        None => Err(invalid_data("too many channels for a WAV file")),
+    }
+}
+
+fn invalid_input(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, message)
+}
+
+fn invalid_data(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// The block alignment and the byte rate of the `fmt ` chunk, or `None` if they
+/// do not fit into it.
+fn frame_sizes(spec: &WavSpec) -> Option<(u16, u32)> {
+    let block_align = spec.block_align();
+    if block_align > u16::MAX as u32 {
+        return None;
+    }
+    spec.sample_rate.checked_mul(block_align).map(|byte_rate| (block_align as u16, byte_rate))
+}
+
+/// Size of the `data` chunk in bytes, or an error if it does not fit into a
+/// WAV file.
+fn data_size(spec: &WavSpec, num_frames: u32) -> std::io::Result<u32> {
+    (num_frames as u64 * spec.block_align() as u64)
+        .checked_add(header_size(spec))
+        // including the pad byte of a chunk of odd size
+        .filter(|&size| size + (size & 1) <= u32::MAX as u64)
+        .map(|size| (size - header_size(spec)) as u32)
+        .ok_or_else(|| invalid_input("too many frames for a WAV file"))
+}
+
+/// Size of everything in front of the sample data in bytes.
+fn header_size(spec: &WavSpec) -> u64 {
+    match spec.format {
+        // RIFF header, `fmt ` chunk and `data` chunk header
+        SampleFormat::Int8 | SampleFormat::Int16 | SampleFormat::Int24 | SampleFormat::Int32 =>
+            12 + 24 + 8,
+        // non-PCM formats additionally need `cbSize` and a `fact` chunk
+        SampleFormat::Float32 => 12 + 26 + 12 + 8,
+    }
+}
+
+fn write_header<W: Write>(sink: &mut W, spec: &WavSpec, num_frames: u32) -> std::io::Result<()> {
+    let data_size = data_size(spec, num_frames)?;
+    let (block_align, byte_rate) = frame_sizes(spec).ok_or_else(|| invalid_input("too many channels for a WAV file"))?;
+    let is_pcm = spec.format.format_tag() == WAVE_FORMAT_PCM;
+
+    sink.write_all(b"RIFF")?;
+    // the RIFF chunk includes the pad byte after a `data` chunk of odd size
+    sink.write_u32::<LittleEndian>(header_size(spec) as u32 - 8 + data_size + (data_size & 1))?;
+    sink.write_all(b"WAVE")?;
+
+    sink.write_all(b"fmt ")?;
+    sink.write_u32::<LittleEndian>(if is_pcm { 16 } else { 18 })?;
+    sink.write_u16::<LittleEndian>(spec.format.format_tag())?;
+    sink.write_u16::<LittleEndian>(spec.channels)?;
+    sink.write_u32::<LittleEndian>(spec.sample_rate)?;
+    sink.write_u32::<LittleEndian>(byte_rate)?;
+    sink.write_u16::<LittleEndian>(block_align)?;
+    sink.write_u16::<LittleEndian>(spec.format.bits_per_sample())?;
+
+    if !is_pcm {
+        sink.write_u16::<LittleEndian>(0)?;
+        sink.write_all(b"fact")?;
+        sink.write_u32::<LittleEndian>(4)?;
+        sink.write_u32::<LittleEndian>(num_frames)?;
+    }
+
+    sink.write_all(b"data")?;
+    sink.write_u32::<LittleEndian>(data_size)
+}
+
+#[test]
+fn test_wav_header_sizes() {
+    use byteorder::ReadBytesExt;
+    use std::io::{Cursor, Read};
+
+    let spec = WavSpec { channels: 2, sample_rate: 44100, format: SampleFormat::Int24 };
+    let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+    for _ in 0..8 {
+        writer.write_frame(&(0.5f32, -0.5f32)).unwrap();
+    }
+    writer.write_samples(&[0.5, -0.5, 0.5, -0.5]).unwrap();
+    assert!(writer.write_samples(&[0.5]).is_err());
+    assert!(writer.write_frame(&0.5f32).is_err());
+    let bytes = writer.finalize().unwrap().into_inner();
+    assert_eq!(bytes.len(), 44 + 10 * 6);
+
+    let mut cursor = Cursor::new(bytes);
+    let mut tag = [0u8; 4];
+    cursor.read_exact(&mut tag).unwrap();
+    assert_eq!(&tag, b"RIFF");
+    assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 36 + 60);
+    cursor.seek(SeekFrom::Start(40)).unwrap();
+    assert_eq!(cursor.read_u32::<LittleEndian>().unwrap(), 60);
+    assert_eq!(cursor.read_i24::<LittleEndian>().unwrap(), I24::MAX / 2);
+
+    // a data chunk of odd size is followed by a pad byte
+    let spec = WavSpec { channels: 1, sample_rate: 44100, format: SampleFormat::Int8 };
+    for &announced in [true, false].iter() {
+        let mut writer = if announced {
+            WavWriter::with_length(Cursor::new(Vec::new()), spec, 3).unwrap()
+        } else {
+            WavWriter::new(Cursor::new(Vec::new()), spec).unwrap()
+        };
+        writer.write_samples(&[0.5, -0.5, 0.0]).unwrap();
+        let bytes = if announced {
+            assert!(writer.write_frame(&0.5f32).is_err());
+            writer.finish().unwrap().into_inner()
+        } else {
+            writer.finalize().unwrap().into_inner()
+        };
+        assert_eq!(bytes.len(), 44 + 4);
+        assert_eq!(&bytes[4..8], &(36u32 + 4).to_le_bytes());
+        assert_eq!(&bytes[40..44], &3u32.to_le_bytes());
+        assert_eq!(bytes[47], 0);
+        let reader = WavReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.len(), 3);
+    }
+
+    // only files of known length can be finished without seeking
+    let writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+    assert!(writer.finish().is_err());
+
+    // the frame size and the byte rate must fit into the fmt chunk
+    let too_wide = WavSpec { channels: 20000, sample_rate: 44100, format: SampleFormat::Float32 };
+    assert_eq!(too_wide.block_align(), 80000);
+    assert!(WavWriter::new(Cursor::new(Vec::new()), too_wide).is_err());
+    assert!(WavWriter::with_length(Cursor::new(Vec::new()), too_wide, 0).is_err());
+    let too_fast = WavSpec { channels: 2, sample_rate: u32::MAX, format: SampleFormat::Int16 };
+    assert!(WavWriter::with_length(Cursor::new(Vec::new()), too_fast, 0).is_err());
+    let widest = WavSpec { channels: 16383, sample_rate: 44100, format: SampleFormat::Float32 };
+    let bytes = WavWriter::with_length(Cursor::new(Vec::new()), widest, 0).unwrap().finish().unwrap().into_inner();
+    assert_eq!(WavReader::new(Cursor::new(bytes.clone())).unwrap().spec(), &widest);
+
+    // and must agree with the block alignment stored in the file
+    let mut wrong_channels = bytes.clone();
+    wrong_channels[22..24].copy_from_slice(&20000u16.to_le_bytes());
+    assert!(WavReader::new(Cursor::new(wrong_channels)).is_err());
+    let mut wrong_block_align = bytes;
+    wrong_block_align[32..34].copy_from_slice(&4u16.to_le_bytes());
+    assert!(WavReader::new(Cursor::new(wrong_block_align)).is_err());
+}
+
+#[test]
+fn test_wav_round_trip() {
+    use std::io::Cursor;
+
+    let frames = [(0.0f32, 1.0f32), (-1.0, 0.5), (0.25, -0.25)];
+    let formats = [SampleFormat::Int8, SampleFormat::Int16, SampleFormat::Int24,
+                   SampleFormat::Int32, SampleFormat::Float32];
+    for &format in formats.iter() {
+        let spec = WavSpec { channels: 2, sample_rate: 48000, format };
+        let mut writer = WavWriter::new(Cursor::new(Vec::new()), spec).unwrap();
+        for frame in frames.iter() {
+            writer.write_frame(frame).unwrap();
+        }
+        let bytes = writer.finalize().unwrap().into_inner();
+
+        let mut reader = WavReader::new(Cursor::new(bytes)).unwrap();
+        assert_eq!(reader.spec(), &spec);
+        assert_eq!(reader.len(), frames.len() as u32);
+        for frame in frames.iter() {
+            let (left, right) = reader.read_frame::<(f32, f32)>().unwrap().unwrap();
+            assert!((left - frame.0).abs() < 0.01, "{:?}: {} != {}", format, left, frame.0);
+            assert!((right - frame.1).abs() < 0.01, "{:?}: {} != {}", format, right, frame.1);
+        }
+        assert!(reader.read_frame::<(f32, f32)>().unwrap().is_none());
+    }
+}
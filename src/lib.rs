@@ -8,3 +8,10 @@ pub mod noise;
 pub mod filters;
 pub mod knob;
 pub mod data;
+pub mod io;
+pub mod midi;
+pub mod sampler;
+pub mod envelope;
+pub mod voice;
+pub mod tuning;
+pub mod patch;
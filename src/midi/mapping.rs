@@ -0,0 +1,99 @@
+//! Driving knobs with MIDI controllers.
+
+use std::ops::{Add, Div, Mul, Sub};
+
+use knob::Knob;
+use super::{MidiMessage, MidiSink};
+
+/// How the controller value is mapped to the range of a knob.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ControlCurve {
+    /// Equal steps of the controller change the value by equal amounts.
+    Linear,
+    /// Equal steps of the controller change the value by equal ratios, which
+    /// suits frequencies and gains. Both ends of the range must be positive.
+    Exponential,
+}
+
+impl ControlCurve {
+    /// Map a position between `0` and `1` into the given range.
+    pub fn apply<T>(self, position: f32, min: T, max: T) -> T where
+        T: Copy + Add<Output=T> + Sub<Output=T> + Mul<f32, Output=T> + Div<T, Output=f32>
+    {
+        match self {
+            ControlCurve::Linear => min + (max - min) * position,
+            ControlCurve::Exponential => min * (max / min).powf(position),
+        }
+    }
+}
+
+struct Binding {
+    channel: Option<u8>,
+    controller: u8,
+    /// Sets the knob to the value for a controller position between `0` and `1`.
+    update: Box<dyn FnMut(f32)>,
+}
+
+/// Sets knobs according to control change messages.
+///
+/// Each binding maps the value of a controller, optionally only on one
+/// channel, into a range of knob values.
+#[derive(Default)]
+pub struct ControllerMap {
+    bindings: Vec<Binding>,
+}
+
+impl ControllerMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Let the controller move the knob between `min` and `max`.
+    pub fn bind<T>(mut self, channel: Option<u8>, controller: u8, mut knob: Knob<T>, min: T, max: T,
+                   curve: ControlCurve) -> Self where
+        T: Copy + Add<Output=T> + Sub<Output=T> + Mul<f32, Output=T> + Div<T, Output=f32> + 'static
+    {
+        self.bindings.push(Binding {
+            channel,
+            controller,
+            update: Box::new(move |position| knob.set(curve.apply(position, min, max))),
+        });
+        self
+    }
+}
+
+impl MidiSink for ControllerMap {
+    fn handle(&mut self, message: &MidiMessage) {
+        if let MidiMessage::ControlChange { channel, controller, value } = *message {
+            let position = value as f32 / 127.0;
+            for binding in self.bindings.iter_mut() {
+                if binding.controller == controller && binding.channel.is_none_or(|c| c == channel) {
+                    (binding.update)(position);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_controller_map() {
+    use foundation::{Frequency, SignalGenerator};
+
+    let (cutoff, volume) = (Knob::new(Frequency::from_hertz(1000.0)), Knob::new(0.5));
+    let (mut cutoff_value, mut volume_value) = (cutoff.as_generator(), volume.as_generator());
+    let mut map = ControllerMap::new()
+        .bind(None, 74, cutoff, Frequency::from_hertz(100.0), Frequency::from_hertz(10000.0),
+              ControlCurve::Exponential)
+        .bind(Some(1), 7, volume, 0.0, 2.0, ControlCurve::Linear);
+
+    map.handle(&MidiMessage::ControlChange { channel: 3, controller: 74, value: 127 });
+    assert!((cutoff_value.next().to_hertz() - 10000.0).abs() < 0.1);
+    map.handle(&MidiMessage::ControlChange { channel: 3, controller: 74, value: 0 });
+    assert!((cutoff_value.next().to_hertz() - 100.0).abs() < 1e-3);
+
+    // bound to another channel
+    map.handle(&MidiMessage::ControlChange { channel: 0, controller: 7, value: 127 });
+    assert_eq!(volume_value.next(), 0.5);
+    map.handle(&MidiMessage::ControlChange { channel: 1, controller: 7, value: 127 });
+    assert_eq!(volume_value.next(), 2.0);
+}
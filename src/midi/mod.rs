@@ -0,0 +1,94 @@
+//! Musical events in the MIDI format and ways of getting them into a patch.
+
+use voice::Keyboard;
+
+pub mod mapping;
+pub mod smf;
+pub mod stream;
+
+pub use self::mapping::{ControllerMap, ControlCurve};
+pub use self::stream::MidiParser;
+
+/// A channel voice message. Channels are numbered from `0` to `15`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    PolyAftertouch { channel: u8, note: u8, pressure: u8 },
+    ControlChange { channel: u8, controller: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    ChannelAftertouch { channel: u8, pressure: u8 },
+    /// The bend ranges from `-8192` to `8191`, zero is the center.
+    PitchBend { channel: u8, bend: i16 },
+}
+
+impl MidiMessage {
+    /// The number of data bytes following the given status byte, or `None` if
+    /// it does not start a channel voice message.
+    pub fn data_len(status: u8) -> Option<usize> {
+        match status & 0xF0 {
+            0x80 | 0x90 | 0xA0 | 0xB0 | 0xE0 => Some(2),
+            0xC0 | 0xD0 => Some(1),
+            _ => None,
+        }
+    }
+
+    /// Decode a message from its status byte and data bytes. The second data
+    /// byte is ignored for messages with only one.
+    pub fn from_bytes(status: u8, data1: u8, data2: u8) -> Option<MidiMessage> {
+        let channel = status & 0x0F;
+        let (data1, data2) = (data1 & 0x7F, data2 & 0x7F);
+        let message = match status & 0xF0 {
+            0x80 => MidiMessage::NoteOff { channel, note: data1, velocity: data2 },
+            0x90 => MidiMessage::NoteOn { channel, note: data1, velocity: data2 },
+            0xA0 => MidiMessage::PolyAftertouch { channel, note: data1, pressure: data2 },
+            0xB0 => MidiMessage::ControlChange { channel, controller: data1, value: data2 },
+            0xC0 => MidiMessage::ProgramChange { channel, program: data1 },
+            0xD0 => MidiMessage::ChannelAftertouch { channel, pressure: data1 },
+            0xE0 => MidiMessage::PitchBend { channel, bend: ((data2 as i16) << 7 | data1 as i16) - 8192 },
+            _ => return None,
+        };
+        Some(message)
+    }
+
+    pub fn channel(&self) -> u8 {
+        match *self {
+            MidiMessage::NoteOff { channel, .. } |
+            MidiMessage::NoteOn { channel, .. } |
+            MidiMessage::PolyAftertouch { channel, .. } |
+            MidiMessage::ControlChange { channel, .. } |
+            MidiMessage::ProgramChange { channel, .. } |
+            MidiMessage::ChannelAftertouch { channel, .. } |
+            MidiMessage::PitchBend { channel, .. } => channel,
+        }
+    }
+}
+
+/// Something reacting to MIDI messages.
+pub trait MidiSink {
+    fn handle(&mut self, message: &MidiMessage);
+}
+
+impl<F: FnMut(&MidiMessage)> MidiSink for F {
+    fn handle(&mut self, message: &MidiMessage) {
+        self(message)
+    }
+}
+
+/// Plays the notes on a polyphonic generator. A note on message with zero
+/// velocity is a note off message.
+impl MidiSink for Keyboard {
+    fn handle(&mut self, message: &MidiMessage) {
+        match *message {
+            MidiMessage::NoteOn { note, velocity, .. } if velocity > 0 =>
+                self.note_on(note, velocity as f32 / 127.0),
+            MidiMessage::NoteOn { note, .. } | MidiMessage::NoteOff { note, .. } =>
+                self.note_off(note),
+            // all sound off and all notes off
+            MidiMessage::ControlChange { controller: 120, .. } |
+            MidiMessage::ControlChange { controller: 123, .. } =>
+                self.all_notes_off(),
+            _ => {},
+        }
+    }
+}
@@ -0,0 +1,444 @@
+//! Reading and playing Standard MIDI Files.
+
+use std;
+use std::io::Read;
+use std::path::Path;
+
+use byteorder::{BigEndian, ReadBytesExt};
+
+use foundation::{Duration, SignalGenerator, SoundModule, SamplingParameters};
+use super::{MidiMessage, MidiSink};
+
+/// Tempo assumed until the first tempo event, in microseconds per quarter note.
+const DEFAULT_TEMPO: u32 = 500_000;
+
+/// How the ticks of the event times relate to time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Division {
+    /// Ticks per quarter note, the duration of which is given by the tempo.
+    TicksPerQuarter(u16),
+    /// Ticks per frame of SMPTE time code, independent of the tempo. The frame
+    /// rate 29 stands for 29.97 frames per second (drop frame).
+    Smpte { frames_per_second: u8, ticks_per_frame: u8 },
+}
+
+/// An event carrying information about the song instead of sound.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetaEvent {
+    /// Microseconds per quarter note.
+    Tempo(u32),
+    TimeSignature { numerator: u8, denominator: u8, clocks_per_click: u8, thirty_seconds_per_quarter: u8 },
+    /// The number of sharps (positive) or flats (negative), and whether the
+    /// key is minor.
+    KeySignature { accidentals: i8, minor: bool },
+    TrackName(String),
+    /// Any of the other text events, such as lyrics or markers.
+    Text { kind: u8, text: String },
+    EndOfTrack,
+    Other { kind: u8, data: Vec<u8> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    Midi(MidiMessage),
+    Meta(MetaEvent),
+    SysEx(Vec<u8>),
+}
+
+/// An event of a track at an absolute time in ticks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackEvent {
+    pub tick: u64,
+    pub kind: EventKind,
+}
+
+/// A MIDI message at an absolute time.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimedMessage {
+    /// The time in seconds, in double precision so that messages stay sample
+    /// accurate in long files.
+    pub seconds: f64,
+    pub track: usize,
+    pub message: MidiMessage,
+}
+
+/// The contents of a Standard MIDI File.
+///
+/// In format 0 files, there is only one track. In format 1 files, all tracks
+/// play simultaneously and share the tempo map of the first track. Format 2
+/// files containing independent sequences are read, but their tracks are
+/// played simultaneously, too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MidiFile {
+    pub format: u16,
+    pub division: Division,
+    pub tracks: Vec<Vec<TrackEvent>>,
+}
+
+impl MidiFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<MidiFile> {
+        MidiFile::read(std::io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    pub fn read<R: Read>(mut source: R) -> std::io::Result<MidiFile> {
+        let mut bytes = Vec::new();
+        source.read_to_end(&mut bytes)?;
+        MidiFile::parse(&bytes)
+    }
+
+    pub fn parse(mut bytes: &[u8]) -> std::io::Result<MidiFile> {
+        let (tag, header) = read_chunk(&mut bytes)?;
+        if &tag != b"MThd" || header.len() < 6 {
+            return Err(invalid_data("not a standard MIDI file"));
+        }
+        let mut header = header;
+        let format = header.read_u16::<BigEndian>()?;
+        let num_tracks = header.read_u16::<BigEndian>()?;
+        let division = header.read_u16::<BigEndian>()?;
+        let division = if division & 0x8000 == 0 {
+            Division::TicksPerQuarter(division)
+        } else {
+            // the frame rate is stored as a negative number
+            let frames_per_second = ((division >> 8) as u8).wrapping_neg();
+            if ![24, 25, 29, 30].contains(&frames_per_second) {
+                return Err(invalid_data("invalid SMPTE frame rate"));
+            }
+            Division::Smpte {
+                frames_per_second,
+                ticks_per_frame: division as u8,
+            }
+        };
+        if format > 2 {
+            return Err(invalid_data("unsupported MIDI file format"));
+        }
+
+        let mut tracks = Vec::with_capacity(num_tracks as usize);
+        while tracks.len() < num_tracks as usize {
+            let (tag, data) = read_chunk(&mut bytes)?;
+            // unknown chunks must be ignored
+            if &tag == b"MTrk" {
+                tracks.push(parse_track(data)?);
+            }
+        }
+        Ok(MidiFile { format, division, tracks })
+    }
+
+    /// All MIDI messages of all tracks in the order they are played, with
+    /// their times computed from the tempo map.
+    pub fn messages(&self) -> Vec<TimedMessage> {
+        // merge the tracks, keeping the order of simultaneous events
+        let mut events: Vec<(usize, &TrackEvent)> = self.tracks.iter().enumerate()
+            .flat_map(|(track, events)| events.iter().map(move |event| (track, event)))
+            .collect();
+        events.sort_by_key(|&(_, event)| event.tick);
+
+        let mut tempo = DEFAULT_TEMPO;
+        let (mut last_tick, mut last_seconds) = (0, 0.0f64);
+        let mut messages = Vec::new();
+        for (track, event) in events {
+            let seconds = last_seconds + self.tick_duration(tempo) * (event.tick - last_tick) as f64;
+            last_tick = event.tick;
+            last_seconds = seconds;
+            match event.kind {
+                EventKind::Meta(MetaEvent::Tempo(new_tempo)) => tempo = new_tempo,
+                EventKind::Midi(message) => messages.push(TimedMessage {
+                    seconds,
+                    track,
+                    message,
+                }),
+                _ => {},
+            }
+        }
+        messages
+    }
+
+    /// The duration of one tick in seconds at the given tempo.
+    fn tick_duration(&self, tempo: u32) -> f64 {
+        match self.division {
+            Division::TicksPerQuarter(ticks) => tempo as f64 * 1e-6 / ticks.max(1) as f64,
+            Division::Smpte { frames_per_second, ticks_per_frame } => {
+                let frame_rate = if frames_per_second == 29 { 29.97 } else { frames_per_second as f64 };
+                1.0 / (frame_rate * ticks_per_frame.max(1) as f64)
+            },
+        }
+    }
+}
+
+/// Read the tag and contents of the next chunk.
+fn read_chunk<'a>(bytes: &mut &'a [u8]) -> std::io::Result<([u8; 4], &'a [u8])> {
+    let mut tag = [0; 4];
+    bytes.read_exact(&mut tag)?;
+    let size = bytes.read_u32::<BigEndian>()? as usize;
+    if size > bytes.len() {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "truncated chunk"));
+    }
+    let (data, rest) = bytes.split_at(size);
+    *bytes = rest;
+    Ok((tag, data))
+}
+
+/// Read a variable length quantity of up to four bytes.
+fn read_varlen(bytes: &mut &[u8]) -> std::io::Result<u32> {
+    let mut value = 0u32;
+    for _ in 0..4 {
+        let byte = bytes.read_u8()?;
+        value = (value << 7) | (byte & 0x7F) as u32;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("variable length quantity too long"))
+}
+
+fn read_bytes<'a>(bytes: &mut &'a [u8], len: usize) -> std::io::Result<&'a [u8]> {
+    if len > bytes.len() {
+        return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "truncated event"));
+    }
+    let (data, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(data)
+}
+
+fn parse_track(mut bytes: &[u8]) -> std::io::Result<Vec<TrackEvent>> {
+    let mut events = Vec::new();
+    let mut tick = 0u64;
+    let mut running_status = None;
+    while !bytes.is_empty() {
+        tick += read_varlen(&mut bytes)? as u64;
+        let first = bytes.read_u8()?;
+        let kind = match first {
+            0xFF => {
+                running_status = None;
+                let kind = bytes.read_u8()?;
+                let len = read_varlen(&mut bytes)? as usize;
+                EventKind::Meta(parse_meta(kind, read_bytes(&mut bytes, len)?))
+            },
+            0xF0 | 0xF7 => {
+                running_status = None;
+                let len = read_varlen(&mut bytes)? as usize;
+                EventKind::SysEx(read_bytes(&mut bytes, len)?.to_vec())
+            },
+            _ => {
+                // data bytes continue the previous channel message
+                let (status, data1) = if first & 0x80 != 0 {
+                    (first, bytes.read_u8()?)
+                } else {
+                    (running_status.ok_or_else(|| invalid_data("data byte without status"))?, first)
+                };
+                let data2 = match MidiMessage::data_len(status) {
+                    Some(2) => bytes.read_u8()?,
+                    Some(_) => 0,
+                    None => return Err(invalid_data("unexpected system message")),
+                };
+                running_status = Some(status);
+                EventKind::Midi(MidiMessage::from_bytes(status, data1, data2).unwrap())
+            },
+        };
+        let end = kind == EventKind::Meta(MetaEvent::EndOfTrack);
+        events.push(TrackEvent { tick, kind });
+        if end {
+            break;
+        }
+    }
+    Ok(events)
+}
+
+fn parse_meta(kind: u8, data: &[u8]) -> MetaEvent {
+    let text = || String::from_utf8_lossy(data).into_owned();
+    match (kind, data.len()) {
+        (0x51, 3) => MetaEvent::Tempo((data[0] as u32) << 16 | (data[1] as u32) << 8 | data[2] as u32),
+        (0x58, 4) => MetaEvent::TimeSignature {
+            numerator: data[0],
+            denominator: 1u8.checked_shl(data[1] as u32).unwrap_or(0),
+            clocks_per_click: data[2],
+            thirty_seconds_per_quarter: data[3],
+        },
+        (0x59, 2) => MetaEvent::KeySignature { accidentals: data[0] as i8, minor: data[1] != 0 },
+        (0x03, _) => MetaEvent::TrackName(text()),
+        (0x01..=0x0F, _) => MetaEvent::Text { kind, text: text() },
+        (0x2F, _) => MetaEvent::EndOfTrack,
+        _ => MetaEvent::Other { kind, data: data.to_vec() },
+    }
+}
+
+fn invalid_data(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Sends the messages of a MIDI file to sinks at the sample they are due.
+///
+/// The player has to be advanced once per sample, which `play` does for a
+/// generator, so that notes and controller changes reach e.g. a `Keyboard`
+/// or knobs before the generator computes the sample.
+pub struct MidiPlayer {
+    messages: Vec<TimedMessage>,
+    /// The sample index of each message at the current sample rate.
+    sample_times: Vec<u64>,
+    sinks: Vec<(Option<u8>, Box<dyn MidiSink>)>,
+    position: u64,
+    next_message: usize,
+}
+
+impl MidiPlayer {
+    pub fn new(file: &MidiFile) -> Self {
+        MidiPlayer::from_messages(file.messages())
+    }
+
+    /// Play the given messages, which must be ordered by time.
+    pub fn from_messages(messages: Vec<TimedMessage>) -> Self {
+        MidiPlayer {
+            sample_times: vec![0; messages.len()],
+            messages,
+            sinks: Vec::new(),
+            position: 0,
+            next_message: 0,
+        }
+    }
+
+    /// Send the messages of the given channel, or of all channels, to the sink.
+    pub fn with_sink<S>(mut self, channel: Option<u8>, sink: S) -> Self where
+        S: MidiSink + 'static
+    {
+        self.sinks.push((channel, Box::new(sink)));
+        self
+    }
+
+    /// Drive the given generator, advancing the player before each sample.
+    pub fn play<S: SignalGenerator>(self, generator: S) -> Sequenced<S> {
+        Sequenced {
+            player: self,
+            generator,
+        }
+    }
+
+    /// The time of the last message.
+    pub fn duration(&self) -> Duration {
+        Duration::from_seconds(self.messages.last().map_or(0.0, |message| message.seconds) as f32)
+    }
+
+    /// Return true if all messages have been sent.
+    pub fn is_finished(&self) -> bool {
+        self.next_message == self.messages.len()
+    }
+
+    /// Send the messages due at the current sample and move to the next one.
+    pub fn advance(&mut self) {
+        while self.next_message < self.messages.len() && self.sample_times[self.next_message] <= self.position {
+            let message = self.messages[self.next_message].message;
+            for &mut (channel, ref mut sink) in self.sinks.iter_mut() {
+                if channel.is_none_or(|channel| channel == message.channel()) {
+                    sink.handle(&message);
+                }
+            }
+            self.next_message += 1;
+        }
+        self.position += 1;
+    }
+}
+
+impl SoundModule for MidiPlayer {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        for (sample_time, message) in self.sample_times.iter_mut().zip(self.messages.iter()) {
+            *sample_time = (params.sample_rate().to_hertz() as f64 * message.seconds).round() as u64;
+        }
+    }
+
+    /// Rewind to the beginning.
+    fn reset(&mut self) {
+        self.position = 0;
+        self.next_message = 0;
+    }
+}
+
+/// A generator driven by a MIDI player.
+pub struct Sequenced<S> {
+    player: MidiPlayer,
+    generator: S,
+}
+
+impl<S> Sequenced<S> {
+    pub fn player(&self) -> &MidiPlayer {
+        &self.player
+    }
+}
+
+impl<S: SoundModule> SoundModule for Sequenced<S> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.player.set_sampling_parameters(params);
+        self.generator.set_sampling_parameters(params);
+    }
+
+    fn reset(&mut self) {
+        self.player.reset();
+        self.generator.reset();
+    }
+}
+
+impl<S: SignalGenerator> SignalGenerator for Sequenced<S> {
+    type Output = S::Output;
+
+    #[inline(always)]
+    fn next(&mut self) -> Self::Output {
+        self.player.advance();
+        self.generator.next()
+    }
+}
+
+#[test]
+fn test_midi_file() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use foundation::Frequency;
+
+    let file: Vec<u8> = [
+        // format 1, two tracks, 96 ticks per quarter note
+        &b"MThd\x00\x00\x00\x06\x00\x01\x00\x02\x00\x60"[..],
+        // tempo of 240 bpm after one quarter note
+        b"MTrk\x00\x00\x00\x0B\x60\xFF\x51\x03\x03\xD0\x90\x00\xFF\x2F\x00",
+        // two notes using running status, the first one ended by zero velocity
+        b"MTrk\x00\x00\x00\x1A\x00\xFF\x03\x04Bass\x00\x91\x24\x64\x00\x28\x50\x60\x24\x00\x60\x81\x28\x00\x00\xFF\x2F\x00",
+    ].concat();
+    let midi = MidiFile::parse(&file).unwrap();
+    assert_eq!(midi.division, Division::TicksPerQuarter(96));
+    assert_eq!(midi.tracks[1][0].kind, EventKind::Meta(MetaEvent::TrackName("Bass".to_string())));
+
+    // SMPTE divisions only come in the standard frame rates
+    let smpte = MidiFile::parse(b"MThd\x00\x00\x00\x06\x00\x00\x00\x00\xE7\x28").unwrap();
+    assert_eq!(smpte.division, Division::Smpte { frames_per_second: 25, ticks_per_frame: 40 });
+    assert!(MidiFile::parse(b"MThd\x00\x00\x00\x06\x00\x00\x00\x00\x80\x28").is_err());
+    assert!(MidiFile::parse(b"MThd\x00\x00\x00\x06\x00\x00\x00\x00\xE6\x28").is_err());
+
+    let times: Vec<f64> = midi.messages().iter().map(|message| message.seconds).collect();
+    assert_eq!(times, vec![0.0, 0.0, 0.5, 0.75]);
+
+    let received = Rc::new(RefCell::new(Vec::new()));
+    let log = received.clone();
+    let mut player = MidiPlayer::new(&midi).with_sink(Some(1), move |message: &MidiMessage| {
+        log.borrow_mut().push(*message);
+    }).play(0.0);
+    player.set_sampling_parameters(&SamplingParameters::with_rate(Frequency::from_hertz(100.0)));
+    (0..50).for_each(|_| { player.next(); });
+    assert_eq!(received.borrow().len(), 2);
+    player.next();
+    assert_eq!(received.borrow().last(), Some(&MidiMessage::NoteOn { channel: 1, note: 36, velocity: 0 }));
+    (0..25).for_each(|_| { player.next(); });
+    assert_eq!(received.borrow().last(), Some(&MidiMessage::NoteOff { channel: 1, note: 40, velocity: 0 }));
+    assert!(player.player().is_finished());
+}
+
+#[test]
+fn test_sample_accurate_times() {
+    use foundation::Frequency;
+
+    // one tick is 250 samples at 120 bpm, 96 ticks per quarter note and 48 kHz,
+    // so the note lies exactly one tick after 30 minutes
+    let note = MidiMessage::NoteOn { channel: 0, note: 60, velocity: 100 };
+    let midi = MidiFile {
+        format: 0,
+        division: Division::TicksPerQuarter(96),
+        tracks: vec![vec![TrackEvent { tick: 96 * 2 * 60 * 30 + 1, kind: EventKind::Midi(note) }]],
+    };
+    let mut player = MidiPlayer::new(&midi);
+    player.set_sampling_parameters(&SamplingParameters::with_rate(Frequency::from_hertz(48000.0)));
+    assert_eq!(player.sample_times, vec![48000 * 60 * 30 + 250]);
+}
@@ -0,0 +1,104 @@
+//! Decoding MIDI messages from a stream of bytes, as sent by hardware.
+
+use super::MidiMessage;
+
+/// Decodes channel voice messages from a raw MIDI byte stream.
+///
+/// Bytes are fed one at a time, as they arrive. Running status is supported,
+/// i.e. the status byte may be left out when it equals the previous one.
+/// System exclusive and system common messages are skipped, and real-time
+/// messages may appear anywhere without interrupting other messages.
+#[derive(Debug, Clone, Default)]
+pub struct MidiParser {
+    running_status: Option<u8>,
+    data: [u8; 2],
+    data_count: usize,
+    /// Number of data bytes of a system message that are still to be skipped.
+    skip: usize,
+    in_sysex: bool,
+}
+
+impl MidiParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Process the next byte, returning a message if it was completed.
+    pub fn parse(&mut self, byte: u8) -> Option<MidiMessage> {
+        match byte {
+            // real-time messages do not affect the state
+            0xF8..=0xFF => None,
+            0xF0 => {
+                self.running_status = None;
+                self.in_sysex = true;
+                None
+            },
+            0xF7 => {
+                self.in_sysex = false;
+                None
+            },
+            0xF1..=0xF6 => {
+                self.running_status = None;
+                self.in_sysex = false;
+                self.skip = match byte {
+                    0xF1 | 0xF3 => 1,
+                    0xF2 => 2,
+                    _ => 0,
+                };
+                None
+            },
+            0x80..=0xEF => {
+                self.running_status = Some(byte);
+                self.data_count = 0;
+                self.skip = 0;
+                self.in_sysex = false;
+                None
+            },
+            _ => self.parse_data(byte),
+        }
+    }
+
+    /// Process several bytes, returning all completed messages.
+    pub fn parse_bytes(&mut self, bytes: &[u8]) -> Vec<MidiMessage> {
+        bytes.iter().filter_map(|&byte| self.parse(byte)).collect()
+    }
+
+    fn parse_data(&mut self, byte: u8) -> Option<MidiMessage> {
+        if self.in_sysex {
+            return None;
+        }
+        if self.skip > 0 {
+            self.skip -= 1;
+            return None;
+        }
+        let status = self.running_status?;
+        let data_len = MidiMessage::data_len(status)?;
+        self.data[self.data_count] = byte;
+        self.data_count += 1;
+        if self.data_count < data_len {
+            return None;
+        }
+        self.data_count = 0;
+        MidiMessage::from_bytes(status, self.data[0], self.data[1])
+    }
+}
+
+#[test]
+fn test_midi_parser() {
+    let mut parser = MidiParser::new();
+    let bytes = [
+        // note on with running status, interrupted by a timing clock
+        0x90, 0x3C, 0x64, 0x40, 0xF8, 0x50,
+        // stray data bytes of a sysex message are ignored
+        0xF0, 0x7E, 0x01, 0xF7, 0x01,
+        // program change and pitch bend
+        0xC2, 0x05, 0xE0, 0x00, 0x40, 0x7F, 0x7F,
+    ];
+    assert_eq!(parser.parse_bytes(&bytes), vec![
+        MidiMessage::NoteOn { channel: 0, note: 60, velocity: 100 },
+        MidiMessage::NoteOn { channel: 0, note: 64, velocity: 80 },
+        MidiMessage::ProgramChange { channel: 2, program: 5 },
+        MidiMessage::PitchBend { channel: 0, bend: 0 },
+        MidiMessage::PitchBend { channel: 0, bend: 8191 },
+    ]);
+}
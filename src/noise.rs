@@ -1,8 +1,9 @@
 use std;
 use std::fmt::Debug;
-use rand::{Rng, NewRng, SeedableRng, XorShiftRng};
+use rand::{Rng, FromEntropy, SeedableRng, XorShiftRng};
 
 use foundation::{SignalGenerator, Sample, SoundModule, SamplingParameters};
+use foundation::generator::BLOCK_SIZE;
 
 pub fn white_noise() -> Noise<White, XorShiftRng> {
     Noise::new(White)
@@ -20,6 +21,13 @@ pub struct Noise<C, R: SeedableRng> {
 pub trait NoiseColor {
     fn reset(&mut self);
     fn next<R>(&mut self, rng: &mut R) -> f32 where R: Rng;
+
+    /// Generate a block of samples, producing the same values as `next`.
+    fn fill<R>(&mut self, rng: &mut R, output: &mut [f32]) where R: Rng {
+        for sample in output.iter_mut() {
+            *sample = self.next(rng);
+        }
+    }
 }
 
 impl<C, R> Debug for Noise<C, R> where
@@ -68,14 +76,18 @@ impl<C: NoiseColor, R: Rng + SeedableRng> SignalGenerator for Noise<C, R> where
     fn next(&mut self) -> Self::Output {
         self.color.next(&mut self.rng)
     }
+
+    fn fill(&mut self, output: &mut [f32]) {
+        self.color.fill(&mut self.rng, output)
+    }
 }
 
-impl<C, R: NewRng> Noise<C, R> where
+impl<C, R: SeedableRng> Noise<C, R> where
     R::Seed: Clone
 {
     pub fn new(color: C) -> Self {
         Noise {
-            rng: NewRng::new(),
+            rng: FromEntropy::from_entropy(),
             color: color
         }
     }
@@ -91,6 +103,25 @@ impl NoiseColor for White {
     fn next<R>(&mut self, rng: &mut R) -> f32 where R: Rng {
         rng.gen_range(<f32 as Sample>::lower_limit(), <f32 as Sample>::upper_limit())
     }
+
+    fn fill<R>(&mut self, rng: &mut R, output: &mut [f32]) where R: Rng {
+        // the same conversion as `gen_range`, but on a whole block of random
+        // bits so that it can be vectorized
+        let (lower, upper) = (<f32 as Sample>::lower_limit(), <f32 as Sample>::upper_limit());
+        let (scale, offset) = (upper - lower, 2.0 * lower - upper);
+        let mut bits = [0u32; BLOCK_SIZE];
+        for output in output.chunks_mut(BLOCK_SIZE) {
+            let bits = &mut bits[..output.len()];
+            for bits in bits.iter_mut() {
+                *bits = rng.next_u32();
+            }
+            for (sample, &bits) in output.iter_mut().zip(bits.iter()) {
+                // a value between one and two from the upper 23 bits
+                let one_to_two = f32::from_bits(bits >> 9 | 0x3f80_0000);
+                *sample = one_to_two * scale + offset;
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
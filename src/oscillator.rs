@@ -1,7 +1,8 @@
 use std;
 
 use foundation::{Frequency, SignalGenerator, SoundModule, SamplingParameters};
-use waveform::{Waveform, Saw, Sine, Rect, Triangle};
+use foundation::generator::BLOCK_SIZE;
+use waveform::{Waveform, Saw, Sine, Rect, Triangle, BlSaw, BlPulse, BlTriangle};
 
 pub fn sine<F>(frequency: F) -> Oscillator<Sine, F> where
     F: SignalGenerator<Output=Frequency>
@@ -33,6 +34,35 @@ pub fn rect<F>(duty_cycle: f32, frequency: F) -> Oscillator<Rect, F> where
     Oscillator::new(frequency, Rect(duty_cycle))
 }
 
+/// A saw oscillator with reduced aliasing.
+pub fn bl_saw<F>(frequency: F) -> Oscillator<BlSaw, F> where
+    F: SignalGenerator<Output=Frequency>
+{
+    Oscillator::new(frequency, BlSaw)
+}
+
+/// A triangle oscillator with reduced aliasing.
+pub fn bl_triangle<F>(frequency: F) -> Oscillator<BlTriangle, F> where
+    F: SignalGenerator<Output=Frequency>
+{
+    Oscillator::new(frequency, BlTriangle)
+}
+
+/// A square oscillator with reduced aliasing.
+pub fn bl_square<F>(frequency: F) -> Oscillator<BlPulse, F> where
+    F: SignalGenerator<Output=Frequency>
+{
+    Oscillator::new(frequency, BlPulse(0.5))
+}
+
+/// A pulse oscillator with reduced aliasing that is high for the given fraction
+/// of each period.
+pub fn bl_pulse<F>(duty_cycle: f32, frequency: F) -> Oscillator<BlPulse, F> where
+    F: SignalGenerator<Output=Frequency>
+{
+    Oscillator::new(frequency, BlPulse(duty_cycle))
+}
+
 #[derive(Debug, Clone)]
 pub struct Oscillator<Shape, Freq> {
     phase: f32,
@@ -73,8 +103,22 @@ impl<Shape, Freq> SignalGenerator for Oscillator<Shape, Freq> where
     type Output = f32;
 
     fn next(&mut self) -> f32 {
-        let value = self.shape.phase_amplitude(self.phase);
-        self.phase = (self.phase + self.frequency.next() / self.samples_per_second).fract();
+        let phase_increment = self.frequency.next() / self.samples_per_second;
+        let value = self.shape.sampled_amplitude(self.phase, phase_increment);
+        self.phase = (self.phase + phase_increment).fract();
         value
     }
+
+    fn fill(&mut self, output: &mut [f32]) {
+        let mut frequencies = [Frequency::from_hertz(0.0); BLOCK_SIZE];
+        for output in output.chunks_mut(BLOCK_SIZE) {
+            let frequencies = &mut frequencies[..output.len()];
+            self.frequency.fill(frequencies);
+            for (sample, &frequency) in output.iter_mut().zip(frequencies.iter()) {
+                let phase_increment = frequency / self.samples_per_second;
+                *sample = self.shape.sampled_amplitude(self.phase, phase_increment);
+                self.phase = (self.phase + phase_increment).fract();
+            }
+        }
+    }
 }
@@ -0,0 +1,564 @@
+//! A text format describing patches, so that they can be changed without
+//! recompiling.
+//!
+//! Each line holds one statement, and `#` starts a comment:
+//!
+//! ```text
+//! # a saw through a filter whose cutoff is swept by an LFO
+//! osc = saw(frequency: 110)
+//! vcf = low_pass(q: 2)
+//! osc.out -> vcf.in
+//!
+//! # the cutoff is the sum of a base frequency and the scaled LFO
+//! base = knob(value: 800)
+//! lfo = sine(frequency: 0.5)
+//! depth = gain(amount: 400)
+//! lfo.out -> depth.in
+//! base.out -> vcf.cutoff
+//! depth.out -> vcf.cutoff
+//!
+//! output vcf.out
+//! ```
+//!
+//! A node is defined by its name, the module and its parameters. Parameters
+//! that are also inputs, like the frequency of an oscillator, keep their value
+//! while the input is not connected. Knobs are handed out with the patch, so
+//! that they can be turned while it is playing. Outputs are connected to
+//! inputs with `->`, and inputs connected to several outputs receive their
+//! sum. The order of the statements does not matter.
+//!
+//! Frequencies are given in Hz and times in seconds. The available modules
+//! and their parameters are listed in `MODULES`, and values outside of the
+//! range of a parameter, like negative times, are rejected.
+//!
+//! Patches loaded as voices of a `Polyphony` generator receive the notes they
+//! play from the `voice` module, whose outputs are the `frequency`, the `gate`
+//! (one while the note is held, zero otherwise) and the `velocity`.
+
+use std;
+use std::collections::HashMap;
+use std::path::Path;
+
+use envelope::adsr;
+use filters::{biquad, Delay, Echo, FilteredExt, LadderFilter, LowPassRC};
+use foundation::{Duration, Filter, SignalGenerator, SoundModule, SamplingParameters};
+use foundation::types::units::HZ;
+use foundation::Frequency;
+use knob::{Knob, KnobGenerator};
+use noise::{pink_noise, white_noise};
+use oscillator;
+use voice::VoiceControls;
+use super::{FilterNode, GeneratorNode, Node, Patch, PatchError};
+
+/// The longest time in seconds accepted for envelopes and delays.
+const MAX_TIME: f32 = 60.0;
+
+/// The modules that can be used in a patch file, with their parameters and
+/// default values. Parameters marked as inputs can be connected.
+pub const MODULES: &[(&str, &[Parameter])] = &[
+    ("sine", OSCILLATOR),
+    ("saw", OSCILLATOR),
+    ("square", OSCILLATOR),
+    ("triangle", OSCILLATOR),
+    ("bl_saw", OSCILLATOR),
+    ("bl_square", OSCILLATOR),
+    ("bl_triangle", OSCILLATOR),
+    ("voice", &[]),
+    ("white_noise", &[]),
+    ("pink_noise", &[]),
+    ("knob", &[Parameter::fixed("value", 0.0)]),
+    ("gain", &[Parameter::input("amount", 1.0)]),
+    ("adsr", &[Parameter::input("gate", 0.0), Parameter::fixed("attack", 0.01).range(0.0, MAX_TIME),
+               Parameter::fixed("decay", 0.1).range(0.0, MAX_TIME), Parameter::fixed("sustain", 0.7).range(0.0, 1.0),
+               Parameter::fixed("release", 0.3).range(0.0, MAX_TIME)]),
+    ("lowpass_rc", &[CUTOFF]),
+    ("low_pass", &[CUTOFF, Parameter::input("q", std::f32::consts::FRAC_1_SQRT_2).range(MIN_Q, MAX_Q)]),
+    ("high_pass", &[CUTOFF, Parameter::input("q", std::f32::consts::FRAC_1_SQRT_2).range(MIN_Q, MAX_Q)]),
+    ("band_pass", &[Parameter::input("center", 1000.0).range(0.0, f32::INFINITY),
+                    Parameter::input("q", 1.0).range(MIN_Q, MAX_Q)]),
+    ("ladder", &[CUTOFF, Parameter::input("resonance", 0.0).range(0.0, 1.0)]),
+    ("delay", &[Parameter::fixed("time", 0.5).range(0.0, MAX_TIME)]),
+    ("echo", &[Parameter::fixed("time", 0.5).range(0.0, MAX_TIME), Parameter::fixed("dampening", 0.5).range(0.0, 1.0)]),
+];
+
+const OSCILLATOR: &[Parameter] = &[Parameter::input("frequency", 440.0).range(0.0, f32::INFINITY)];
+const CUTOFF: Parameter = Parameter::input("cutoff", 1000.0).range(0.0, f32::INFINITY);
+/// The range of quality factors of the biquad filters.
+const MIN_Q: f32 = 0.01;
+const MAX_Q: f32 = 100.0;
+
+/// A parameter of a module in a patch file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: &'static str,
+    pub default: f32,
+    /// Whether the parameter is also an input of the node.
+    pub input: bool,
+    /// The range of values accepted in a patch file. Signals connected to an
+    /// input are not restricted.
+    pub min: f32,
+    pub max: f32,
+}
+
+impl Parameter {
+    const fn input(name: &'static str, default: f32) -> Self {
+        Parameter { name, default, input: true, min: f32::NEG_INFINITY, max: f32::INFINITY }
+    }
+
+    const fn fixed(name: &'static str, default: f32) -> Self {
+        Parameter { name, default, input: false, min: f32::NEG_INFINITY, max: f32::INFINITY }
+    }
+
+    const fn range(self, min: f32, max: f32) -> Self {
+        Parameter { min, max, ..self }
+    }
+
+    /// Describe the range for error messages.
+    fn describe_range(&self) -> String {
+        match (self.min.is_finite(), self.max.is_finite()) {
+            (true, true) => format!("between {} and {}", self.min, self.max),
+            (true, false) => format!("at least {}", self.min),
+            (false, true) => format!("at most {}", self.max),
+            (false, false) => "finite".to_string(),
+        }
+    }
+}
+
+/// An error in a patch file, at a line and column counted from one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// A patch read from a patch file, together with the knobs it defines.
+#[derive(Debug)]
+pub struct LoadedPatch {
+    pub patch: Patch,
+    /// The knobs by the names of their nodes.
+    pub knobs: HashMap<String, Knob<f32>>,
+}
+
+/// Read a patch file.
+pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<LoadedPatch> {
+    let text = std::fs::read_to_string(path)?;
+    load(&text).map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+}
+
+/// Build a patch from its description.
+pub fn load(text: &str) -> Result<LoadedPatch, ParseError> {
+    load_with_controls(text, None)
+}
+
+/// Build a patch playing a voice of a `Polyphony` generator.
+pub fn load_voice(text: &str, controls: &VoiceControls) -> Result<LoadedPatch, ParseError> {
+    load_with_controls(text, Some(controls))
+}
+
+fn load_with_controls(text: &str, controls: Option<&VoiceControls>) -> Result<LoadedPatch, ParseError> {
+    let mut definitions = Vec::new();
+    let mut connections = Vec::new();
+    let mut output = None;
+    for (index, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let tokens = tokenize(line, index + 1)?;
+        let mut parser = Parser { tokens: &tokens, position: 0, line: index + 1, end: line.chars().count() + 1 };
+        match parser.statement()? {
+            Some(Statement::Node(definition)) => definitions.push(definition),
+            Some(Statement::Connection(from, to)) => connections.push((from, to)),
+            Some(Statement::Output(port)) => {
+                if output.is_some() {
+                    return Err(port.error("the output is already chosen"));
+                }
+                output = Some(port);
+            },
+            None => {},
+        }
+    }
+
+    let mut patch = Patch::new();
+    let mut knobs = HashMap::new();
+    for definition in definitions.iter() {
+        let node = build_node(definition, controls, &mut knobs)?;
+        patch.add_node(&definition.name.text, node).map_err(|err| definition.name.error(&err.to_string()))?;
+    }
+    for (from, to) in connections.iter() {
+        patch.connect(&from.text, &to.text).map_err(|err| match err {
+            PatchError::UnknownPort(ref port) if *port == to.text => to.error(&err.to_string()),
+            PatchError::UnknownNode(ref node) if to.text.starts_with(&format!("{}.", node)) => to.error(&err.to_string()),
+            PatchError::Cycle { .. } => to.error(&err.to_string()),
+            _ => from.error(&err.to_string()),
+        })?;
+    }
+    match output {
+        Some(port) => patch.set_output(&port.text).map_err(|err| port.error(&err.to_string()))?,
+        None => return Err(ParseError { line: text.lines().count().max(1), column: 1, message: "missing `output`".to_string() }),
+    }
+    Ok(LoadedPatch { patch, knobs })
+}
+
+/// A piece of text with its position.
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+    column: usize,
+}
+
+impl Token {
+    fn error(&self, message: &str) -> ParseError {
+        ParseError { line: self.line, column: self.column, message: message.to_string() }
+    }
+}
+
+fn tokenize(line: &str, line_number: usize) -> Result<Vec<Token>, ParseError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        } else if c.is_alphanumeric() || c == '_' || c == '.' || c == '-' && chars.get(i + 1) != Some(&'>') {
+            // names, ports and numbers
+            i += 1;
+            while i < chars.len() && (chars[i].is_alphanumeric() || "_.+-".contains(chars[i]))
+                && !(chars[i] == '-' && chars.get(i + 1) == Some(&'>'))
+            {
+                i += 1;
+            }
+        } else if c == '-' {
+            i += 2;
+        } else if "=(),:".contains(c) {
+            i += 1;
+        } else {
+            return Err(ParseError { line: line_number, column: start + 1, message: format!("unexpected character `{}`", c) });
+        }
+        tokens.push(Token { text: chars[start..i].iter().collect(), line: line_number, column: start + 1 });
+    }
+    Ok(tokens)
+}
+
+struct Definition {
+    name: Token,
+    module: Token,
+    parameters: Vec<(Token, Token)>,
+}
+
+enum Statement {
+    Node(Definition),
+    Connection(Token, Token),
+    Output(Token),
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    line: usize,
+    /// The column after the end of the line.
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn statement(&mut self) -> Result<Option<Statement>, ParseError> {
+        let first = match self.next() {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        let statement = if first.text == "output" && self.tokens.len() == 2 {
+            Statement::Output(self.port()?)
+        } else if self.peek() == Some("->") {
+            self.position += 1;
+            Statement::Connection(check_port(first)?, self.port()?)
+        } else {
+            let name = check_name(first)?;
+            self.expect("=")?;
+            let module = check_name(self.expect_any("a module")?)?;
+            let mut parameters = Vec::new();
+            if self.peek() == Some("(") {
+                self.position += 1;
+                while self.peek() != Some(")") {
+                    let parameter = check_name(self.expect_any("a parameter or `)`")?)?;
+                    self.expect(":")?;
+                    let value = self.expect_any("a number")?;
+                    parameters.push((parameter, value.clone()));
+                    if self.peek() != Some(")") {
+                        let separator = self.expect_any("`,` or `)`")?;
+                        if separator.text != "," {
+                            return Err(separator.error(&format!("expected `,` or `)`, found `{}`", separator.text)));
+                        }
+                    }
+                }
+                self.position += 1;
+            }
+            Statement::Node(Definition { name, module, parameters })
+        };
+        match self.next() {
+            Some(token) => Err(token.error(&format!("unexpected `{}`", token.text))),
+            None => Ok(Some(statement)),
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.position);
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|token| token.text.as_str())
+    }
+
+    fn expect_any(&mut self, expected: &str) -> Result<&'a Token, ParseError> {
+        let end = ParseError { line: self.line, column: self.end, message: format!("expected {}", expected) };
+        self.next().ok_or(end)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<&'a Token, ParseError> {
+        let token = self.expect_any(&format!("`{}`", expected))?;
+        if token.text == expected {
+            Ok(token)
+        } else {
+            Err(token.error(&format!("expected `{}`, found `{}`", expected, token.text)))
+        }
+    }
+
+    fn port(&mut self) -> Result<Token, ParseError> {
+        check_port(self.expect_any("a port")?)
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_alphabetic() || c == '_') && text.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+fn check_name(token: &Token) -> Result<Token, ParseError> {
+    if is_name(&token.text) {
+        Ok(token.clone())
+    } else {
+        Err(token.error(&format!("expected a name, found `{}`", token.text)))
+    }
+}
+
+fn check_port(token: &Token) -> Result<Token, ParseError> {
+    let mut parts = token.text.splitn(2, '.');
+    match (parts.next(), parts.next()) {
+        (Some(node), Some(port)) if is_name(node) && is_name(port) => Ok(token.clone()),
+        _ => Err(token.error(&format!("expected a port like `node.out`, found `{}`", token.text))),
+    }
+}
+
+/// The values of the parameters of a node, in the order of the module
+/// definition.
+fn parameter_values(definition: &Definition, parameters: &[Parameter]) -> Result<Vec<f32>, ParseError> {
+    let mut values: Vec<Option<f32>> = vec![None; parameters.len()];
+    for (name, value) in definition.parameters.iter() {
+        let index = parameters.iter().position(|parameter| parameter.name == name.text).ok_or_else(|| {
+            let names: Vec<_> = parameters.iter().map(|parameter| parameter.name).collect();
+            let expected = if names.is_empty() { "none".to_string() } else { names.join(", ") };
+            name.error(&format!("unknown parameter `{}` of `{}`, expected one of: {}", name.text, definition.module.text, expected))
+        })?;
+        if values[index].is_some() {
+            return Err(name.error(&format!("parameter `{}` is given twice", name.text)));
+        }
+        let number = value.text.parse::<f32>().ok().filter(|number| number.is_finite())
+            .ok_or_else(|| value.error(&format!("expected a number for `{}`, found `{}`", name.text, value.text)))?;
+        let parameter = &parameters[index];
+        if number < parameter.min || number > parameter.max {
+            return Err(value.error(&format!("`{}` must be {}, found `{}`", name.text, parameter.describe_range(), value.text)));
+        }
+        values[index] = Some(number);
+    }
+    Ok(values.iter().zip(parameters.iter()).map(|(value, parameter)| value.unwrap_or(parameter.default)).collect())
+}
+
+type Inputs = Vec<(&'static str, Knob<f32>)>;
+
+fn generator_node<G: SignalGenerator<Output=f32> + 'static>(inputs: Inputs, generator: G) -> Box<dyn Node> {
+    Box::new(inputs.into_iter().fold(GeneratorNode::new(generator), |node, (name, knob)| node.with_input(name, knob)))
+}
+
+fn filter_node<F: Filter<Input=f32, Output=f32> + 'static>(inputs: Inputs, filter: F) -> Box<dyn Node> {
+    Box::new(inputs.into_iter().fold(FilterNode::new(filter), |node, (name, knob)| node.with_input(name, knob)))
+}
+
+fn build_node(definition: &Definition, controls: Option<&VoiceControls>, knobs: &mut HashMap<String, Knob<f32>>)
+    -> Result<Box<dyn Node>, ParseError>
+{
+    let module = definition.module.text.as_str();
+    let parameters = MODULES.iter().find(|&&(name, _)| name == module).map(|&(_, parameters)| parameters)
+        .ok_or_else(|| definition.module.error(&format!("unknown module `{}`", module)))?;
+    let values = parameter_values(definition, parameters)?;
+    // knobs for the parameters that are inputs, in the order of the module definition
+    let inputs: Inputs = parameters.iter().zip(values.iter())
+        .filter(|&(parameter, _)| parameter.input)
+        .map(|(parameter, &value)| (parameter.name, Knob::new(value)))
+        .collect();
+    let generators: Vec<KnobGenerator<f32>> = inputs.iter().map(|(_, knob)| knob.as_generator()).collect();
+    let input = |index: usize| generators[index].clone();
+    let hertz = |index: usize| input(index).map(|x| x * HZ);
+    let seconds = Duration::from_seconds;
+
+    let node = match module {
+        "sine" => generator_node(inputs, oscillator::sine(hertz(0))),
+        "saw" => generator_node(inputs, oscillator::saw(hertz(0))),
+        "square" => generator_node(inputs, oscillator::square(hertz(0))),
+        "triangle" => generator_node(inputs, oscillator::triangle(hertz(0))),
+        "bl_saw" => generator_node(inputs, oscillator::bl_saw(hertz(0))),
+        "bl_square" => generator_node(inputs, oscillator::bl_square(hertz(0))),
+        "bl_triangle" => generator_node(inputs, oscillator::bl_triangle(hertz(0))),
+        "voice" => {
+            let controls = controls.ok_or_else(|| definition.module.error("`voice` is only available when playing notes"))?;
+            Box::new(Voice {
+                frequency: controls.frequency(),
+                gate: controls.gate(),
+                velocity: controls.velocity(),
+            })
+        },
+        "white_noise" => generator_node(inputs, white_noise()),
+        "pink_noise" => generator_node(inputs, pink_noise()),
+        "knob" => {
+            let knob = Knob::new(values[0]);
+            let node = generator_node(inputs, knob.as_generator());
+            knobs.insert(definition.name.text.clone(), knob);
+            node
+        },
+        "gain" => Box::new(Gain { amount: values[0] }),
+        "adsr" => {
+            let gate = input(0).map(|x| x > 0.0);
+            generator_node(inputs, adsr(gate, seconds(values[1]), seconds(values[2]), values[3], seconds(values[4])))
+        },
+        "lowpass_rc" => filter_node(inputs, LowPassRC::new(hertz(0))),
+        "low_pass" => filter_node(inputs, biquad::low_pass(hertz(0), input(1))),
+        "high_pass" => filter_node(inputs, biquad::high_pass(hertz(0), input(1))),
+        "band_pass" => filter_node(inputs, biquad::band_pass(hertz(0), input(1))),
+        "ladder" => filter_node(inputs, LadderFilter::new(hertz(0), input(1))),
+        "delay" => filter_node(inputs, Delay::new(seconds(values[0]))),
+        "echo" => filter_node(inputs, Echo::new(seconds(values[0]), values[1])),
+        _ => unreachable!("module `{}` is listed but not built", module),
+    };
+    Ok(node)
+}
+
+/// Provides the note played by a voice.
+#[derive(Debug)]
+struct Voice {
+    frequency: KnobGenerator<Frequency>,
+    gate: KnobGenerator<bool>,
+    velocity: KnobGenerator<f32>,
+}
+
+impl SoundModule for Voice {
+    fn set_sampling_parameters(&mut self, _params: &SamplingParameters) {}
+
+    fn reset(&mut self) {}
+}
+
+impl Node for Voice {
+    fn inputs(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn outputs(&self) -> Vec<&str> {
+        vec!["frequency", "gate", "velocity"]
+    }
+
+    fn tick(&mut self, _inputs: &[Option<f32>], outputs: &mut [f32]) {
+        outputs[0] = self.frequency.next().to_hertz();
+        outputs[1] = if self.gate.next() { 1.0 } else { 0.0 };
+        outputs[2] = self.velocity.next();
+    }
+}
+
+/// Multiplies its input `in` by the input `amount`.
+#[derive(Debug, Clone)]
+struct Gain {
+    amount: f32,
+}
+
+impl SoundModule for Gain {
+    fn set_sampling_parameters(&mut self, _params: &SamplingParameters) {}
+
+    fn reset(&mut self) {}
+}
+
+impl Node for Gain {
+    fn inputs(&self) -> Vec<&str> {
+        vec!["in", "amount"]
+    }
+
+    fn outputs(&self) -> Vec<&str> {
+        vec!["out"]
+    }
+
+    fn tick(&mut self, inputs: &[Option<f32>], outputs: &mut [f32]) {
+        if let Some(amount) = inputs[1] {
+            self.amount = amount;
+        }
+        outputs[0] = inputs[0].unwrap_or(0.0) * self.amount;
+    }
+}
+
+#[test]
+fn test_patch_format() {
+    use foundation::generator::constant;
+    use voice::Polyphony;
+
+    let text = "# a filtered saw\n\
+                vcf = low_pass(cutoff: 800, q: 2)\n\
+                osc = saw(frequency: 110)\n\
+                level = knob(value: 0.5)\n\
+                vca = gain\n\
+                \n\
+                osc.out -> vcf.in  # connected before the gain is defined\n\
+                vcf.out -> vca.in\n\
+                level.out -> vca.amount\n\
+                output vca.out\n";
+    let LoadedPatch { mut patch, mut knobs } = load(text).unwrap();
+    let mut reference = oscillator::saw(constant(110. * HZ))
+        .filtered(biquad::low_pass(constant(800. * HZ), 2.0));
+    let params = SamplingParameters::audio_cd();
+    patch.set_sampling_parameters(&params);
+    reference.set_sampling_parameters(&params);
+    for _ in 0..100 {
+        assert_eq!(patch.next(), reference.next() * 0.5);
+    }
+    knobs.get_mut("level").unwrap().set(0.0);
+    assert_eq!(patch.next(), 0.0);
+
+    let error = |text: &str| load(text).unwrap_err().to_string();
+    assert_eq!(error("osc = saww(frequency: 1)\noutput osc.out"), "line 1, column 7: unknown module `saww`");
+    assert_eq!(error("osc = saw(frequncy: 1)\noutput osc.out"),
+               "line 1, column 11: unknown parameter `frequncy` of `saw`, expected one of: frequency");
+    assert_eq!(error("osc = saw(frequency: fast)"), "line 1, column 22: expected a number for `frequency`, found `fast`");
+    assert_eq!(error("env = adsr(sustain: 1.5)"), "line 1, column 21: `sustain` must be between 0 and 1, found `1.5`");
+    assert_eq!(error("echo = echo(time: 1e9)"), "line 1, column 19: `time` must be between 0 and 60, found `1e9`");
+    assert_eq!(error("vcf = low_pass(q: -1)"), "line 1, column 19: `q` must be between 0.01 and 100, found `-1`");
+    assert_eq!(error("osc = saw(frequency: -5)"), "line 1, column 22: `frequency` must be at least 0, found `-5`");
+    assert_eq!(error("osc = saw(frequency 1)"), "line 1, column 21: expected `:`, found `1`");
+    assert_eq!(error("osc = saw(frequency: 1"), "line 1, column 23: expected `,` or `)`");
+    assert_eq!(error("osc = saw\nosc.out -> vcf.in\noutput osc.out"), "line 2, column 12: unknown node `vcf`");
+    assert_eq!(error("osc = saw\nosc.output -> osc.frequency\noutput osc.out"), "line 2, column 1: unknown port `osc.output`");
+    assert_eq!(error("osc = saw\nosc = sine\noutput osc.out"), "line 2, column 1: invalid or duplicate node name `osc`");
+    assert_eq!(error("osc = saw"), "line 1, column 1: missing `output`");
+    let text = "note = voice\nosc = sine\nenv = adsr(attack: 0.001)\nvca = gain\n\
+                note.frequency -> osc.frequency\nnote.gate -> env.gate\n\
+                osc.out -> vca.in\nenv.out -> vca.amount\noutput vca.out\n";
+    let mut voices = Polyphony::new(2, |controls| load_voice(text, controls).unwrap().patch);
+    voices.set_sampling_parameters(&params);
+    assert_eq!(voices.next(), 0.0);
+    voices.note_on(69, 1.0);
+    assert!((0..100).map(|_| voices.next().abs()).fold(0.0, f32::max) > 0.5);
+    assert_eq!(error("note = voice\noutput note.gate"), "line 1, column 8: `voice` is only available when playing notes");
+}
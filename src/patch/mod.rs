@@ -0,0 +1,454 @@
+//! Patches assembled at runtime from nodes connected by named ports.
+//!
+//! Unlike the statically typed combinators like `Filtered` and `Chain`, a
+//! `Patch` can be built from a description that is only known at runtime, e.g.
+//! read from a file. All signals flowing between nodes are mono `f32` signals.
+
+use std;
+use std::collections::HashMap;
+
+use foundation::{BoxedFilter, BoxedGenerator, Filter, SignalGenerator, SoundModule, SamplingParameters};
+use knob::Knob;
+
+pub mod format;
+
+/// A sound module with named input and output ports that can be part of a
+/// `Patch`.
+pub trait Node: SoundModule {
+    fn inputs(&self) -> Vec<&str>;
+
+    fn outputs(&self) -> Vec<&str>;
+
+    /// Compute one sample of all outputs. Inputs that are not connected are
+    /// `None`, those connected to several outputs receive their sum.
+    fn tick(&mut self, inputs: &[Option<f32>], outputs: &mut [f32]);
+}
+
+/// A node generating a signal on its output `out`. Additional inputs set knobs
+/// controlling the generator, and keep the value of the knob while they are not
+/// connected.
+#[derive(Debug)]
+pub struct GeneratorNode<G> {
+    generator: G,
+    knobs: Vec<(String, Knob<f32>)>,
+}
+
+/// A node filtering its input `in` into its output `out`. Additional inputs set
+/// knobs controlling the filter, and keep the value of the knob while they are
+/// not connected.
+#[derive(Debug)]
+pub struct FilterNode<F> {
+    filter: F,
+    knobs: Vec<(String, Knob<f32>)>,
+}
+
+impl<G> GeneratorNode<G> where
+    G: SignalGenerator<Output=f32>
+{
+    pub fn new(generator: G) -> Self {
+        GeneratorNode {
+            generator,
+            knobs: Vec::new(),
+        }
+    }
+
+    /// Add an input setting the knob.
+    pub fn with_input(mut self, name: &str, knob: Knob<f32>) -> Self {
+        self.knobs.push((name.to_string(), knob));
+        self
+    }
+}
+
+impl<G: SoundModule> SoundModule for GeneratorNode<G> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.generator.set_sampling_parameters(params);
+    }
+
+    fn reset(&mut self) {
+        self.generator.reset();
+    }
+}
+
+impl<G> Node for GeneratorNode<G> where
+    G: SignalGenerator<Output=f32>
+{
+    fn inputs(&self) -> Vec<&str> {
+        self.knobs.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    fn outputs(&self) -> Vec<&str> {
+        vec!["out"]
+    }
+
+    fn tick(&mut self, inputs: &[Option<f32>], outputs: &mut [f32]) {
+        set_knobs(&mut self.knobs, inputs);
+        outputs[0] = self.generator.next();
+    }
+}
+
+impl<F> FilterNode<F> where
+    F: Filter<Input=f32, Output=f32>
+{
+    pub fn new(filter: F) -> Self {
+        FilterNode {
+            filter,
+            knobs: Vec::new(),
+        }
+    }
+
+    /// Add an input setting the knob.
+    pub fn with_input(mut self, name: &str, knob: Knob<f32>) -> Self {
+        self.knobs.push((name.to_string(), knob));
+        self
+    }
+}
+
+impl<F: SoundModule> SoundModule for FilterNode<F> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.filter.set_sampling_parameters(params);
+    }
+
+    fn reset(&mut self) {
+        self.filter.reset();
+    }
+}
+
+impl<F> Node for FilterNode<F> where
+    F: Filter<Input=f32, Output=f32>
+{
+    fn inputs(&self) -> Vec<&str> {
+        std::iter::once("in").chain(self.knobs.iter().map(|(name, _)| name.as_str())).collect()
+    }
+
+    fn outputs(&self) -> Vec<&str> {
+        vec!["out"]
+    }
+
+    fn tick(&mut self, inputs: &[Option<f32>], outputs: &mut [f32]) {
+        set_knobs(&mut self.knobs, &inputs[1..]);
+        outputs[0] = self.filter.filter(inputs[0].unwrap_or(0.0));
+    }
+}
+
+fn set_knobs(knobs: &mut [(String, Knob<f32>)], inputs: &[Option<f32>]) {
+    for ((_, knob), input) in knobs.iter_mut().zip(inputs.iter()) {
+        if let Some(value) = *input {
+            knob.set(value);
+        }
+    }
+}
+
+/// A generator with the output `out`.
+impl Node for BoxedGenerator<f32> {
+    fn inputs(&self) -> Vec<&str> {
+        Vec::new()
+    }
+
+    fn outputs(&self) -> Vec<&str> {
+        vec!["out"]
+    }
+
+    fn tick(&mut self, _inputs: &[Option<f32>], outputs: &mut [f32]) {
+        outputs[0] = self.next();
+    }
+}
+
+/// A filter with the input `in` and the output `out`.
+impl Node for BoxedFilter<f32, f32> {
+    fn inputs(&self) -> Vec<&str> {
+        vec!["in"]
+    }
+
+    fn outputs(&self) -> Vec<&str> {
+        vec!["out"]
+    }
+
+    fn tick(&mut self, inputs: &[Option<f32>], outputs: &mut [f32]) {
+        outputs[0] = self.filter(inputs[0].unwrap_or(0.0));
+    }
+}
+
+impl<N: Node + ?Sized> Node for Box<N> {
+    fn inputs(&self) -> Vec<&str> {
+        (**self).inputs()
+    }
+
+    fn outputs(&self) -> Vec<&str> {
+        (**self).outputs()
+    }
+
+    fn tick(&mut self, inputs: &[Option<f32>], outputs: &mut [f32]) {
+        (**self).tick(inputs, outputs)
+    }
+}
+
+/// The reasons why a patch cannot be built as requested.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    /// Node names must be unique and must not contain a `.`.
+    InvalidNodeName(String),
+    UnknownNode(String),
+    UnknownPort(String),
+    /// Ports are written as `node.port`.
+    InvalidPort(String),
+    /// The connection would create a cycle, whose nodes could not be computed
+    /// one after another.
+    Cycle { from: String, to: String },
+}
+
+impl std::fmt::Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            PatchError::InvalidNodeName(ref name) => write!(f, "invalid or duplicate node name `{}`", name),
+            PatchError::UnknownNode(ref name) => write!(f, "unknown node `{}`", name),
+            PatchError::UnknownPort(ref port) => write!(f, "unknown port `{}`", port),
+            PatchError::InvalidPort(ref port) => write!(f, "invalid port `{}`, expected `node.port`", port),
+            PatchError::Cycle { ref from, ref to } => write!(f, "connecting `{}` to `{}` creates a cycle", from, to),
+        }
+    }
+}
+
+impl std::error::Error for PatchError {}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Connection {
+    /// The index of the output among the outputs of all nodes.
+    output: usize,
+    /// The node and the index of its input.
+    node: usize,
+    input: usize,
+}
+
+struct Entry {
+    name: String,
+    node: Box<dyn Node>,
+    /// The outputs of the node among the outputs of all nodes.
+    outputs: std::ops::Range<usize>,
+    /// The outputs connected to each input.
+    sources: Vec<Vec<usize>>,
+}
+
+/// A patch of nodes connected by their ports, generating the signal of one of
+/// their outputs.
+///
+/// The nodes are computed one after another, each after all nodes it receives
+/// signals from, so connections must not form cycles.
+pub struct Patch {
+    nodes: Vec<Entry>,
+    names: HashMap<String, usize>,
+    /// The node owning each output.
+    output_nodes: Vec<usize>,
+    connections: Vec<Connection>,
+    /// The order in which the nodes are computed.
+    order: Vec<usize>,
+    output: Option<usize>,
+    values: Vec<f32>,
+    input_buffer: Vec<Option<f32>>,
+}
+
+impl std::fmt::Debug for Patch {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Patch")
+            .field("nodes", &self.nodes.iter().map(|entry| &entry.name).collect::<Vec<_>>())
+            .field("connections", &self.connections)
+            .field("output", &self.output)
+            .finish()
+    }
+}
+
+impl Default for Patch {
+    fn default() -> Self {
+        Patch::new()
+    }
+}
+
+impl Patch {
+    pub fn new() -> Self {
+        Patch {
+            nodes: Vec::new(),
+            names: HashMap::new(),
+            output_nodes: Vec::new(),
+            connections: Vec::new(),
+            order: Vec::new(),
+            output: None,
+            values: Vec::new(),
+            input_buffer: Vec::new(),
+        }
+    }
+
+    /// Add a node under a unique name.
+    pub fn add_node<N: Node + 'static>(&mut self, name: &str, node: N) -> Result<(), PatchError> {
+        if name.is_empty() || name.contains('.') || self.names.contains_key(name) {
+            return Err(PatchError::InvalidNodeName(name.to_string()));
+        }
+        let node: Box<dyn Node> = Box::new(node);
+        let (num_inputs, num_outputs) = (node.inputs().len(), node.outputs().len());
+        let index = self.nodes.len();
+        self.nodes.push(Entry {
+            name: name.to_string(),
+            node,
+            outputs: self.output_nodes.len()..self.output_nodes.len() + num_outputs,
+            sources: vec![Vec::new(); num_inputs],
+        });
+        self.names.insert(name.to_string(), index);
+        self.output_nodes.extend(std::iter::repeat_n(index, num_outputs));
+        self.values.resize(self.output_nodes.len(), 0.0);
+        self.input_buffer.resize(self.input_buffer.len().max(num_inputs), None);
+        self.order.push(index);
+        Ok(())
+    }
+
+    /// Connect an output to an input, both written as `node.port`.
+    pub fn connect(&mut self, from: &str, to: &str) -> Result<(), PatchError> {
+        let output = self.output_index(from)?;
+        let (node, input) = self.input_index(to)?;
+        let connection = Connection { output, node, input };
+        if self.connections.contains(&connection) {
+            return Ok(());
+        }
+        self.connections.push(connection);
+        match self.sort() {
+            Some(order) => {
+                self.order = order;
+                self.nodes[node].sources[input].push(output);
+                Ok(())
+            },
+            None => {
+                self.connections.pop();
+                Err(PatchError::Cycle { from: from.to_string(), to: to.to_string() })
+            },
+        }
+    }
+
+    /// Choose the output whose signal is generated by the patch.
+    pub fn set_output(&mut self, port: &str) -> Result<(), PatchError> {
+        self.output = Some(self.output_index(port)?);
+        Ok(())
+    }
+
+    /// The names of the nodes in the order they are computed.
+    pub fn node_order(&self) -> Vec<&str> {
+        self.order.iter().map(|&index| self.nodes[index].name.as_str()).collect()
+    }
+
+    fn split_port<'a>(&self, port: &'a str) -> Result<(usize, &'a str), PatchError> {
+        let dot = port.find('.').ok_or_else(|| PatchError::InvalidPort(port.to_string()))?;
+        let node = *self.names.get(&port[..dot]).ok_or_else(|| PatchError::UnknownNode(port[..dot].to_string()))?;
+        Ok((node, &port[dot + 1..]))
+    }
+
+    fn output_index(&self, port: &str) -> Result<usize, PatchError> {
+        let (node, name) = self.split_port(port)?;
+        let entry = &self.nodes[node];
+        let index = entry.node.outputs().iter().position(|&output| output == name)
+            .ok_or_else(|| PatchError::UnknownPort(port.to_string()))?;
+        Ok(entry.outputs.start + index)
+    }
+
+    fn input_index(&self, port: &str) -> Result<(usize, usize), PatchError> {
+        let (node, name) = self.split_port(port)?;
+        let index = self.nodes[node].node.inputs().iter().position(|&input| input == name)
+            .ok_or_else(|| PatchError::UnknownPort(port.to_string()))?;
+        Ok((node, index))
+    }
+
+    /// Sort the nodes topologically, or return `None` if there is a cycle.
+    fn sort(&self) -> Option<Vec<usize>> {
+        let mut dependencies = vec![0; self.nodes.len()];
+        for connection in self.connections.iter() {
+            dependencies[connection.node] += 1;
+        }
+        // nodes without dependencies are computed in the order they were added
+        let mut ready: Vec<usize> = (0..self.nodes.len()).rev().filter(|&node| dependencies[node] == 0).collect();
+        let mut order = Vec::with_capacity(self.nodes.len());
+        while let Some(node) = ready.pop() {
+            order.push(node);
+            for connection in self.connections.iter().filter(|c| self.output_nodes[c.output] == node) {
+                dependencies[connection.node] -= 1;
+                if dependencies[connection.node] == 0 {
+                    ready.push(connection.node);
+                }
+            }
+        }
+        if order.len() == self.nodes.len() { Some(order) } else { None }
+    }
+}
+
+impl SoundModule for Patch {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        for entry in self.nodes.iter_mut() {
+            entry.node.set_sampling_parameters(params);
+        }
+    }
+
+    fn reset(&mut self) {
+        for entry in self.nodes.iter_mut() {
+            entry.node.reset();
+        }
+        for value in self.values.iter_mut() {
+            *value = 0.0;
+        }
+    }
+}
+
+impl SignalGenerator for Patch {
+    type Output = f32;
+
+    fn next(&mut self) -> f32 {
+        let Patch { ref mut nodes, ref order, ref mut values, ref mut input_buffer, .. } = *self;
+        for &index in order.iter() {
+            let entry = &mut nodes[index];
+            let inputs = &mut input_buffer[..entry.sources.len()];
+            for (input, sources) in inputs.iter_mut().zip(entry.sources.iter()) {
+                *input = if sources.is_empty() {
+                    None
+                } else {
+                    Some(sources.iter().map(|&source| values[source]).sum())
+                };
+            }
+            entry.node.tick(inputs, &mut values[entry.outputs.clone()]);
+        }
+        self.output.map_or(0.0, |output| self.values[output])
+    }
+}
+
+#[test]
+fn test_patch() {
+    use foundation::generator::constant;
+    use foundation::types::units::HZ;
+    use filters::{FilteredExt, LowPassRC};
+    use oscillator::{saw, sine};
+
+    let mut patch = Patch::new();
+    let frequency = Knob::new(0.0);
+    let osc = GeneratorNode::new(saw(frequency.as_generator().map(|x| x * HZ)))
+        .with_input("frequency", frequency);
+    let cutoff = Knob::new(200.0);
+    let filter = FilterNode::new(LowPassRC::new(cutoff.as_generator().map(|x| x * HZ)).boxed())
+        .with_input("cutoff", cutoff);
+
+    // nodes are sorted by their connections, not by the order they were added in
+    patch.add_node("filter", filter).unwrap();
+    patch.add_node("osc", osc).unwrap();
+    patch.add_node("lfo", sine(constant(1. * HZ)).map(|x| 110. + 10. * x).boxed()).unwrap();
+    patch.set_output("filter.out").unwrap();
+    patch.connect("lfo.out", "osc.frequency").unwrap();
+    patch.connect("osc.out", "filter.in").unwrap();
+    assert_eq!(patch.node_order(), vec!["lfo", "osc", "filter"]);
+
+    assert_eq!(patch.add_node("osc", 0.0f32.boxed()), Err(PatchError::InvalidNodeName("osc".to_string())));
+    assert_eq!(patch.connect("osc.out", "filter.resonance"), Err(PatchError::UnknownPort("filter.resonance".to_string())));
+    assert_eq!(patch.connect("vcf.out", "osc.frequency"), Err(PatchError::UnknownNode("vcf".to_string())));
+    assert_eq!(patch.connect("filter.out", "osc.frequency"),
+               Err(PatchError::Cycle { from: "filter.out".to_string(), to: "osc.frequency".to_string() }));
+
+    // the patch computes the same signal as the nested generators
+    let mut reference = saw(sine(constant(1. * HZ)).map(|x| (110. + 10. * x) * HZ))
+        .filtered(LowPassRC::new(constant(200. * HZ)));
+    let params = SamplingParameters::audio_cd();
+    patch.set_sampling_parameters(&params);
+    reference.set_sampling_parameters(&params);
+    for _ in 0..1000 {
+        assert_eq!(patch.next(), reference.next());
+    }
+}
@@ -0,0 +1,249 @@
+//! Playback of recorded audio such as drum hits, field recordings or impulse
+//! responses.
+
+use std;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::rc::Rc;
+
+use foundation::{Frequency, Duration, SignalGenerator, SoundModule, SamplingParameters, Sample, Interleaved};
+use io::wav::WavReader;
+
+/// Interleaved audio data held in memory. Clones share the same samples, so
+/// many players can use the same buffer.
+#[derive(Debug, Clone)]
+pub struct AudioBuffer {
+    channels: usize,
+    sample_rate: Frequency,
+    samples: Rc<Vec<f32>>
+}
+
+impl AudioBuffer {
+    pub fn new(channels: usize, sample_rate: Frequency, samples: Vec<f32>) -> Self {
+        assert!(channels > 0, "audio buffer needs at least one channel");
+        assert!(samples.len().is_multiple_of(channels), "audio buffer contains incomplete frames");
+        AudioBuffer {
+            channels,
+            sample_rate,
+            samples: Rc::new(samples)
+        }
+    }
+
+    /// Decode a WAV file.
+    pub fn from_wav<R: Read>(source: R) -> std::io::Result<Self> {
+        let reader = WavReader::new(source)?;
+        let spec = *reader.spec();
+        let samples = reader.into_samples()?;
+        Ok(Self::new(spec.channels as usize, Frequency::from_hertz(spec.sample_rate as f32), samples))
+    }
+
+    /// Load a WAV file from disk.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::from_wav(std::io::BufReader::new(std::fs::File::open(path)?))
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn sample_rate(&self) -> Frequency {
+        self.sample_rate
+    }
+
+    /// The number of frames in the buffer.
+    pub fn len(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.len() as f32 / self.sample_rate
+    }
+
+    /// Return the sample of a channel in the given frame.
+    #[inline(always)]
+    pub fn sample(&self, frame: usize, channel: usize) -> f32 {
+        self.samples[frame * self.channels + channel]
+    }
+}
+
+/// Determines what happens when playback reaches the end of a region. Regions
+/// are given as half-open ranges of frames.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LoopMode {
+    /// Play the buffer once and output silence afterwards.
+    Off,
+    /// Jump back to `start` when reaching `end`.
+    Forward { start: usize, end: usize },
+    /// Alternate between playing forwards and backwards between `start` and `end`.
+    PingPong { start: usize, end: usize },
+}
+
+/// A generator playing back an audio buffer.
+///
+/// The playback rate is a factor relative to the original speed, so that `2.0`
+/// plays the buffer an octave higher. When the buffer was recorded at a
+/// different sample rate than the one currently used, it is resampled using
+/// linear interpolation.
+///
+/// The output can be a mono or a multi-channel frame. Multi-channel buffers are
+/// mixed down when played back as mono, and channels are repeated when a
+/// buffer has fewer channels than the output.
+#[derive(Debug, Clone)]
+pub struct SamplePlayer<F, Rate> {
+    buffer: AudioBuffer,
+    rate: Rate,
+    loop_mode: LoopMode,
+    /// The current playback position measured in frames of the buffer.
+    position: f64,
+    /// Either `1.0` for playing forwards or `-1.0` for playing backwards.
+    direction: f64,
+    /// The number of buffer frames per output frame at the original speed.
+    rate_ratio: f64,
+    frame: PhantomData<F>
+}
+
+impl<F, Rate> SamplePlayer<F, Rate> where
+    F: Interleaved,
+    Rate: SignalGenerator<Output = f32>
+{
+    pub fn new(buffer: AudioBuffer, rate: Rate) -> Self {
+        SamplePlayer {
+            buffer,
+            rate,
+            loop_mode: LoopMode::Off,
+            position: 0.0,
+            direction: 1.0,
+            rate_ratio: f64::NAN,
+            frame: PhantomData
+        }
+    }
+
+    /// Play the buffer using the given loop mode.
+    pub fn with_loop(mut self, loop_mode: LoopMode) -> Self {
+        match loop_mode {
+            LoopMode::Off => {},
+            LoopMode::Forward { start, end } | LoopMode::PingPong { start, end } => {
+                assert!(start < end && end <= self.buffer.len(), "invalid loop region {}..{}", start, end);
+            }
+        }
+        self.loop_mode = loop_mode;
+        self
+    }
+
+    /// Return true when a non-looping buffer has been played completely.
+    pub fn is_finished(&self) -> bool {
+        self.loop_mode == LoopMode::Off
+            && (self.position < 0.0 || self.position >= self.buffer.len() as f64)
+    }
+
+    fn channel_sample(&self, frame: usize, channel: usize) -> f32 {
+        let channels = self.buffer.channels();
+        if F::channels() == 1 && channels > 1 {
+            (0..channels).map(|c| self.buffer.sample(frame, c)).sum::<f32>() / channels as f32
+        } else {
+            self.buffer.sample(frame, channel % channels)
+        }
+    }
+
+    /// The frame following `index` for interpolation purposes.
+    fn next_index(&self, index: usize) -> usize {
+        match self.loop_mode {
+            LoopMode::Forward { start, end } if index + 1 == end => start,
+            LoopMode::PingPong { end, .. } if index + 1 == end => index,
+            _ => (index + 1).min(self.buffer.len() - 1)
+        }
+    }
+
+    fn current_frame(&self) -> F {
+        if self.is_finished() || self.buffer.is_empty() {
+            return F::from_channels(|_| f32::equilibrium());
+        }
+        let index = self.position.floor() as usize;
+        let next = self.next_index(index);
+        let interp = self.position.fract() as f32;
+        F::from_channels(|channel| {
+            (1.0 - interp) * self.channel_sample(index, channel) + interp * self.channel_sample(next, channel)
+        })
+    }
+
+    fn advance(&mut self, rate: f32) {
+        let step = rate as f64 * self.rate_ratio * self.direction;
+        self.position += step;
+        match self.loop_mode {
+            LoopMode::Off => {},
+            LoopMode::Forward { start, end } => {
+                let (start, end) = (start as f64, end as f64);
+                if self.position >= end {
+                    self.position = start + (self.position - end) % (end - start);
+                } else if self.position < start && step < 0.0 {
+                    self.position = end - (start - self.position) % (end - start);
+                }
+            },
+            LoopMode::PingPong { start, end } => {
+                let (start, end) = (start as f64, (end - 1) as f64);
+                if self.position > end {
+                    self.position = (end - (self.position - end)).max(start);
+                    self.direction = -self.direction;
+                } else if self.position < start && step < 0.0 {
+                    self.position = (start + (start - self.position)).min(end);
+                    self.direction = -self.direction;
+                }
+            }
+        }
+    }
+}
+
+impl<F, Rate> SoundModule for SamplePlayer<F, Rate> where
+    Rate: SoundModule
+{
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        self.rate.set_sampling_parameters(params);
+        self.rate_ratio = (self.buffer.sample_rate() / params.sample_rate()) as f64;
+    }
+
+    fn reset(&mut self) {
+        self.rate.reset();
+        self.position = 0.0;
+        self.direction = 1.0;
+    }
+}
+
+impl<F, Rate> SignalGenerator for SamplePlayer<F, Rate> where
+    F: Interleaved,
+    Rate: SignalGenerator<Output = f32>
+{
+    type Output = F;
+
+    fn next(&mut self) -> F {
+        let frame = self.current_frame();
+        let rate = self.rate.next();
+        self.advance(rate);
+        frame
+    }
+}
+
+#[test]
+fn test_sample_player() {
+    let buffer = AudioBuffer::new(1, Frequency::from_hertz(100.0), vec![0.0, 0.25, 0.5, 0.75, 1.0]);
+
+    let mut player: SamplePlayer<f32, _> = SamplePlayer::new(buffer.clone(), 1.0);
+    player.set_sampling_parameters(&SamplingParameters::with_rate(Frequency::from_hertz(200.0)));
+    let output: Vec<f32> = (0..10).map(|_| player.next()).collect();
+    assert_eq!(output, vec![0.0, 0.125, 0.25, 0.375, 0.5, 0.625, 0.75, 0.875, 1.0, 1.0]);
+    assert!(player.is_finished());
+    assert_eq!(player.next(), 0.0);
+
+    player.reset();
+    assert_eq!(player.next(), 0.0);
+    assert_eq!(player.next(), 0.125);
+
+    let mut looped = SamplePlayer::new(buffer, 1.0).with_loop(LoopMode::Forward { start: 1, end: 3 });
+    looped.set_sampling_parameters(&SamplingParameters::with_rate(Frequency::from_hertz(100.0)));
+    let output: Vec<(f32, f32)> = (0..6).map(|_| looped.next()).collect();
+    assert_eq!(output, vec![(0.0, 0.0), (0.25, 0.25), (0.5, 0.5), (0.25, 0.25), (0.5, 0.5), (0.25, 0.25)]);
+}
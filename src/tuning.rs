@@ -0,0 +1,286 @@
+//! Tunings mapping MIDI note numbers to frequencies, read from Scala scale
+//! (`.scl`) and keyboard mapping (`.kbm`) files.
+
+use std;
+use std::io::Read;
+use std::path::Path;
+
+use foundation::{Frequency, Interval, Pitch};
+
+/// The pitches of a scale relative to its tonic, repeating after a period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub description: String,
+    /// The degrees above the tonic in ascending order, the last one being the
+    /// period after which the scale repeats.
+    pub degrees: Vec<Interval>,
+}
+
+impl Scale {
+    /// Divide the period into equal steps, e.g. 19 steps per octave.
+    pub fn equal_temperament(divisions: usize, period: Interval) -> Self {
+        let divisions = divisions.max(1);
+        Scale {
+            description: format!("{} equal divisions of {} cents", divisions, period.to_cents()),
+            degrees: (1..=divisions).map(|step| period * (step as f32 / divisions as f32)).collect(),
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Scale> {
+        Scale::read(std::fs::File::open(path)?)
+    }
+
+    pub fn read<R: Read>(mut source: R) -> std::io::Result<Scale> {
+        let mut text = String::new();
+        source.read_to_string(&mut text)?;
+        Scale::parse(&text)
+    }
+
+    /// Parse the contents of a `.scl` file. Pitches containing a period are
+    /// given in cents, all others are ratios like `3/2` or `2`.
+    pub fn parse(text: &str) -> std::io::Result<Scale> {
+        // the description may be empty, so only comments are skipped before it
+        let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+        let description = lines.next().ok_or_else(|| invalid_data("missing description"))?.trim().to_string();
+        let mut values = lines.filter_map(|line| line.split_whitespace().next());
+        let count = values.next().and_then(|count| count.parse::<usize>().ok())
+            .ok_or_else(|| invalid_data("missing number of notes"))?;
+        let degrees = values.take(count).map(parse_pitch).collect::<std::io::Result<Vec<_>>>()?;
+        if degrees.len() < count {
+            return Err(invalid_data("fewer notes than announced"));
+        }
+        if degrees.is_empty() {
+            return Err(invalid_data("scale without notes"));
+        }
+        Ok(Scale { description, degrees })
+    }
+
+    /// The interval after which the scale repeats.
+    pub fn period(&self) -> Interval {
+        *self.degrees.last().expect("scales have at least one degree")
+    }
+
+    /// The interval of a scale degree above the tonic, continuing the scale
+    /// periodically in both directions.
+    pub fn interval(&self, degree: i32) -> Interval {
+        let len = self.degrees.len() as i32;
+        let (periods, index) = (degree.div_euclid(len), degree.rem_euclid(len));
+        let within = if index == 0 { Interval::from_semitones(0.0) } else { self.degrees[index as usize - 1] };
+        self.period() * periods as f32 + within
+    }
+}
+
+fn parse_pitch(text: &str) -> std::io::Result<Interval> {
+    let invalid = || invalid_data("invalid pitch");
+    let interval = if text.contains('.') {
+        Interval::from_cents(text.parse::<f32>().map_err(|_| invalid())?)
+    } else {
+        let mut parts = text.splitn(2, '/');
+        let numerator = parts.next().and_then(|n| n.parse::<u64>().ok()).ok_or_else(invalid)?;
+        let denominator = match parts.next() {
+            Some(d) => d.parse::<u64>().map_err(|_| invalid())?,
+            None => 1,
+        };
+        if numerator == 0 || denominator == 0 {
+            return Err(invalid());
+        }
+        Interval::from_ratio((numerator as f64 / denominator as f64) as f32)
+    };
+    Ok(interval)
+}
+
+/// Assigns the degrees of a scale to MIDI notes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardMapping {
+    /// The notes outside of this range are not played.
+    pub first_note: u8,
+    pub last_note: u8,
+    /// The note playing the tonic of the scale.
+    pub middle_note: u8,
+    /// The note tuned to the reference frequency.
+    pub reference_note: u8,
+    pub reference_frequency: Frequency,
+    /// The scale degree reached after one repetition of the mapping.
+    pub octave_degree: i32,
+    /// The scale degrees of consecutive notes starting at the middle note,
+    /// `None` for notes that are not played. If empty, every note plays the
+    /// next scale degree.
+    pub mapping: Vec<Option<i32>>,
+}
+
+impl KeyboardMapping {
+    /// Map consecutive notes to consecutive scale degrees.
+    pub fn linear(middle_note: u8, reference_note: u8, reference_frequency: Frequency) -> Self {
+        KeyboardMapping {
+            first_note: 0,
+            last_note: 127,
+            middle_note,
+            reference_note,
+            reference_frequency,
+            octave_degree: 0,
+            mapping: Vec::new(),
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<KeyboardMapping> {
+        KeyboardMapping::read(std::fs::File::open(path)?)
+    }
+
+    pub fn read<R: Read>(mut source: R) -> std::io::Result<KeyboardMapping> {
+        let mut text = String::new();
+        source.read_to_string(&mut text)?;
+        KeyboardMapping::parse(&text)
+    }
+
+    /// Parse the contents of a `.kbm` file. Notes that are not played are
+    /// marked with `x`, missing entries at the end are not played either.
+    pub fn parse(text: &str) -> std::io::Result<KeyboardMapping> {
+        let mut values = text.lines()
+            .filter(|line| !line.starts_with('!'))
+            .filter_map(|line| line.split_whitespace().next());
+        let mut header = |name: &'static str| values.next().ok_or_else(|| invalid_data(name));
+        let note = |text: &str| text.parse::<u8>().ok().filter(|&note| note < 128).ok_or_else(|| invalid_data("invalid note"));
+
+        // only 128 notes can be mapped
+        let size = header("missing map size")?.parse::<usize>().ok()
+            .filter(|&size| size <= 128)
+            .ok_or_else(|| invalid_data("invalid map size"))?;
+        let first_note = note(header("missing first note")?)?;
+        let last_note = note(header("missing last note")?)?;
+        let middle_note = note(header("missing middle note")?)?;
+        let reference_note = note(header("missing reference note")?)?;
+        let reference_frequency = header("missing reference frequency")?.parse::<f32>().ok()
+            .filter(|&hertz| hertz > 0.0)
+            .ok_or_else(|| invalid_data("invalid reference frequency"))?;
+        let octave_degree = header("missing octave degree")?.parse::<i32>()
+            .map_err(|_| invalid_data("invalid octave degree"))?;
+
+        let mut mapping = values.take(size).map(|entry| match entry {
+            "x" | "X" => Ok(None),
+            degree => degree.parse::<i32>().map(Some).map_err(|_| invalid_data("invalid mapping entry")),
+        }).collect::<std::io::Result<Vec<_>>>()?;
+        mapping.resize(size, None);
+
+        let keyboard = KeyboardMapping {
+            first_note,
+            last_note,
+            middle_note,
+            reference_note,
+            reference_frequency: Frequency::from_hertz(reference_frequency),
+            octave_degree,
+            mapping,
+        };
+        if keyboard.degree(reference_note).is_none() {
+            return Err(invalid_data("reference note is not mapped"));
+        }
+        Ok(keyboard)
+    }
+
+    /// The scale degree played by a note, if any.
+    pub fn degree(&self, note: u8) -> Option<i32> {
+        if note < self.first_note || note > self.last_note {
+            return None;
+        }
+        let offset = note as i32 - self.middle_note as i32;
+        if self.mapping.is_empty() {
+            return Some(offset);
+        }
+        let len = self.mapping.len() as i32;
+        self.mapping[offset.rem_euclid(len) as usize]
+            .map(|degree| offset.div_euclid(len) * self.octave_degree + degree)
+    }
+}
+
+/// Maps MIDI note numbers to frequencies using a scale and a keyboard mapping.
+///
+/// The default is twelve-tone equal temperament at concert pitch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    scale: Scale,
+    keyboard: KeyboardMapping,
+}
+
+impl Tuning {
+    pub fn new(scale: Scale, keyboard: KeyboardMapping) -> Self {
+        Tuning { scale, keyboard }
+    }
+
+    /// Equal divisions of the octave with `A4` at 440 Hz and `C4` playing the
+    /// tonic.
+    pub fn equal_temperament(divisions: usize) -> Self {
+        Tuning::new(
+            Scale::equal_temperament(divisions, Interval::from_semitones(12.0)),
+            KeyboardMapping::linear(60, Pitch::A4.midi_note(), Pitch::CONCERT_PITCH),
+        )
+    }
+
+    /// Read a `.scl` file and an optional `.kbm` file. Without a keyboard
+    /// mapping, consecutive notes play consecutive degrees, `C4` plays the
+    /// tonic and `A4` is at 440 Hz.
+    pub fn open<P: AsRef<Path>, Q: AsRef<Path>>(scale: P, keyboard: Option<Q>) -> std::io::Result<Tuning> {
+        let keyboard = match keyboard {
+            Some(path) => KeyboardMapping::open(path)?,
+            None => KeyboardMapping::linear(60, Pitch::A4.midi_note(), Pitch::CONCERT_PITCH),
+        };
+        Ok(Tuning::new(Scale::open(scale)?, keyboard))
+    }
+
+    pub fn scale(&self) -> &Scale {
+        &self.scale
+    }
+
+    pub fn keyboard(&self) -> &KeyboardMapping {
+        &self.keyboard
+    }
+
+    /// The frequency of a note, or `None` if the note is not mapped.
+    pub fn frequency(&self, note: u8) -> Option<Frequency> {
+        let degree = self.keyboard.degree(note)?;
+        let reference = self.keyboard.degree(self.keyboard.reference_note)?;
+        let interval = self.scale.interval(degree) - self.scale.interval(reference);
+        Some(self.keyboard.reference_frequency.transpose(interval))
+    }
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning::equal_temperament(12)
+    }
+}
+
+fn invalid_data(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[test]
+fn test_tuning() {
+    let close = |a: Option<Frequency>, hertz: f32| (a.unwrap().to_hertz() - hertz).abs() < 0.01;
+
+    let standard = Tuning::default();
+    for note in 0..128 {
+        assert!(close(standard.frequency(note), Pitch::from_midi(note).to_frequency(Pitch::CONCERT_PITCH).to_hertz()));
+    }
+
+    let just = Scale::parse("! just.scl\n!\nJust major\n 7\n!\n9/8\n5/4\n4/3\n3/2\n5/3\n15/8\n2/1\n").unwrap();
+    assert_eq!(just.description, "Just major");
+    assert!((just.degrees[3].to_cents() - 701.955).abs() < 0.01);
+    // white keys only, C4 at 261.63 Hz
+    let keyboard = KeyboardMapping::parse("! white.kbm\n12\n0\n127\n60\n60\n261.63\n7\n\
+                                           0\nx\n1\nx\n2\n3\nx\n4\nx\n5\nx\n6\n").unwrap();
+    let tuning = Tuning::new(just, keyboard);
+    assert!(close(tuning.frequency(60), 261.63));
+    assert!(close(tuning.frequency(67), 392.445));
+    assert!(close(tuning.frequency(48 + 9), 218.025));
+    assert!(close(tuning.frequency(72), 523.26));
+    assert_eq!(tuning.frequency(61), None);
+    assert!(KeyboardMapping::parse("128\n0\n127\n60\n60\n261.63\n7\n0\n").is_ok());
+    assert!(KeyboardMapping::parse("2000000000\n0\n127\n60\n60\n261.63\n7\n0\n").is_err());
+
+    let nineteen = Tuning::equal_temperament(19);
+    assert!(close(nineteen.frequency(69 + 19), 880.0));
+    assert!(close(nineteen.frequency(70), 440.0 * 2f32.powf(1.0 / 19.0)));
+
+    assert!(Scale::parse("broken\n2\n3/2\n").is_err());
+    assert!(Scale::parse("broken\n1\n100\n").is_ok());
+    assert!(Scale::parse("broken\n1\nabc\n").is_err());
+}
@@ -0,0 +1,339 @@
+//! Polyphonic playback of a voice generator, allocating one voice per note.
+
+use std;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use foundation::{Duration, Frequency, Pitch, SignalGenerator, SoundModule, SamplingParameters};
+use knob::{Knob, KnobGenerator};
+use tuning::Tuning;
+
+/// Level below which a released voice counts as silent.
+const SILENCE_THRESHOLD: f32 = 1e-4;
+/// Decay time of the level measured for each voice.
+const LEVEL_DECAY: f32 = 0.01;
+
+/// The frequency of a MIDI note number in twelve-tone equal temperament at
+/// concert pitch.
+pub fn note_frequency(note: u8) -> Frequency {
+    Pitch::from_midi(note).into()
+}
+
+/// Which voice is taken over when a note starts while all voices are busy.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stealing {
+    /// The voice whose note started first.
+    Oldest,
+    /// The voice with the lowest output level.
+    Quietest,
+    /// The voice playing the lowest note.
+    Lowest,
+}
+
+/// The knobs through which a voice is played.
+#[derive(Debug)]
+pub struct VoiceControls {
+    frequency: Knob<Frequency>,
+    gate: Knob<bool>,
+    velocity: Knob<f32>,
+}
+
+impl VoiceControls {
+    fn new() -> Self {
+        VoiceControls {
+            frequency: Knob::new(note_frequency(69)),
+            gate: Knob::new(false),
+            velocity: Knob::new(0.0),
+        }
+    }
+
+    /// The frequency of the note played by the voice.
+    pub fn frequency(&self) -> KnobGenerator<Frequency> {
+        self.frequency.as_generator()
+    }
+
+    /// True while the note is held.
+    pub fn gate(&self) -> KnobGenerator<bool> {
+        self.gate.as_generator()
+    }
+
+    /// The velocity of the note, between `0` and `1`.
+    pub fn velocity(&self) -> KnobGenerator<f32> {
+        self.velocity.as_generator()
+    }
+}
+
+/// A note event waiting to be processed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum NoteEvent {
+    On { note: u8, velocity: f32 },
+    Off { note: u8 },
+    AllOff,
+}
+
+/// Plays notes on a `Polyphony` generator from elsewhere, e.g. while the
+/// generator is part of a larger patch. Events take effect with the next
+/// sample generated.
+#[derive(Debug, Clone)]
+pub struct Keyboard {
+    events: Rc<RefCell<Vec<NoteEvent>>>,
+}
+
+impl Keyboard {
+    pub fn note_on(&self, note: u8, velocity: f32) {
+        self.events.borrow_mut().push(NoteEvent::On { note, velocity });
+    }
+
+    pub fn note_off(&self, note: u8) {
+        self.events.borrow_mut().push(NoteEvent::Off { note });
+    }
+
+    pub fn all_notes_off(&self) {
+        self.events.borrow_mut().push(NoteEvent::AllOff);
+    }
+}
+
+#[derive(Debug)]
+struct Voice<V> {
+    generator: V,
+    controls: VoiceControls,
+    /// The note currently assigned to the voice.
+    note: Option<u8>,
+    /// True while the note is held.
+    held: bool,
+    /// True while the voice needs to be computed.
+    active: bool,
+    /// The gate was closed for one sample to restart the envelopes.
+    retrigger: bool,
+    /// Increases with every note, for finding the oldest one.
+    started: u64,
+    level: f32,
+}
+
+/// Plays several notes at once on copies of a voice.
+///
+/// Each voice is built by a function receiving the `VoiceControls` of that
+/// voice, whose generators provide the frequency, gate and velocity of the
+/// note it plays. Voices are only computed while a note is held or still
+/// audible after its release, and their outputs are summed up.
+///
+/// Cloning a finished voice would share its knobs with the clone, so a
+/// function building the voices is required instead.
+#[derive(Debug)]
+pub struct Polyphony<V> {
+    voices: Vec<Voice<V>>,
+    stealing: Stealing,
+    tuning: Tuning,
+    events: Rc<RefCell<Vec<NoteEvent>>>,
+    note_counter: u64,
+    level_decay: f32,
+}
+
+impl<V> Polyphony<V> where
+    V: SignalGenerator<Output=f32>
+{
+    pub fn new<F>(voice_count: usize, mut build: F) -> Self where
+        F: FnMut(&VoiceControls) -> V
+    {
+        let voices = (0..voice_count.max(1)).map(|_| {
+            let controls = VoiceControls::new();
+            Voice {
+                generator: build(&controls),
+                controls,
+                note: None,
+                held: false,
+                active: false,
+                retrigger: false,
+                started: 0,
+                level: 0.0,
+            }
+        }).collect();
+        Polyphony {
+            voices,
+            stealing: Stealing::Oldest,
+            tuning: Tuning::default(),
+            events: Rc::new(RefCell::new(Vec::new())),
+            note_counter: 0,
+            level_decay: 0.0,
+        }
+    }
+
+    pub fn with_stealing(mut self, stealing: Stealing) -> Self {
+        self.stealing = stealing;
+        self
+    }
+
+    /// Play the notes in the given tuning instead of twelve-tone equal
+    /// temperament. Notes the tuning does not map are ignored.
+    pub fn with_tuning(mut self, tuning: Tuning) -> Self {
+        self.tuning = tuning;
+        self
+    }
+
+    /// A handle for playing notes while the generator is owned elsewhere.
+    pub fn keyboard(&self) -> Keyboard {
+        Keyboard {
+            events: self.events.clone(),
+        }
+    }
+
+    /// Start playing a note with a velocity between `0` and `1`.
+    pub fn note_on(&mut self, note: u8, velocity: f32) {
+        let frequency = match self.tuning.frequency(note) {
+            Some(frequency) => frequency,
+            None => return,
+        };
+        let index = self.allocate(note);
+        self.note_counter += 1;
+        let voice = &mut self.voices[index];
+        voice.controls.frequency.set(frequency);
+        voice.controls.velocity.set(velocity.clamp(0.0, 1.0));
+        // close the gate for one sample if it is still open
+        voice.retrigger = voice.controls.gate.get();
+        voice.controls.gate.set(!voice.retrigger);
+        voice.note = Some(note);
+        voice.held = true;
+        voice.active = true;
+        voice.started = self.note_counter;
+    }
+
+    /// Release a note, letting the voice fade out.
+    pub fn note_off(&mut self, note: u8) {
+        for voice in self.voices.iter_mut().filter(|voice| voice.held && voice.note == Some(note)) {
+            voice.held = false;
+            voice.retrigger = false;
+            voice.controls.gate.set(false);
+        }
+    }
+
+    pub fn all_notes_off(&mut self) {
+        for voice in self.voices.iter_mut() {
+            voice.held = false;
+            voice.retrigger = false;
+            voice.controls.gate.set(false);
+        }
+    }
+
+    /// The number of voices that are currently computed.
+    pub fn active_voices(&self) -> usize {
+        self.voices.iter().filter(|voice| voice.active).count()
+    }
+
+    /// The notes that are currently held, in the order of the voices.
+    pub fn held_notes(&self) -> Vec<u8> {
+        self.voices.iter().filter(|voice| voice.held).filter_map(|voice| voice.note).collect()
+    }
+
+    /// Find the voice for a new note: one already playing the same note, an
+    /// inactive one, a released one, or one chosen by the stealing strategy.
+    fn allocate(&self, note: u8) -> usize {
+        let indices = 0..self.voices.len();
+        let same_note = indices.clone().find(|&i| self.voices[i].note == Some(note) && self.voices[i].active);
+        let inactive = || indices.clone().find(|&i| !self.voices[i].active);
+        let released = || indices.clone().filter(|&i| !self.voices[i].held)
+            .min_by(|&a, &b| self.voices[a].level.total_cmp(&self.voices[b].level));
+        let stolen = || match self.stealing {
+            Stealing::Oldest => indices.clone().min_by_key(|&i| self.voices[i].started),
+            Stealing::Quietest => indices.clone().min_by(|&a, &b| self.voices[a].level.total_cmp(&self.voices[b].level)),
+            Stealing::Lowest => indices.clone().min_by_key(|&i| self.voices[i].note),
+        };
+        same_note.or_else(inactive).or_else(released).or_else(stolen).unwrap_or(0)
+    }
+
+    fn process_events(&mut self) {
+        let events = std::mem::take(&mut *self.events.borrow_mut());
+        for event in events {
+            match event {
+                NoteEvent::On { note, velocity } => self.note_on(note, velocity),
+                NoteEvent::Off { note } => self.note_off(note),
+                NoteEvent::AllOff => self.all_notes_off(),
+            }
+        }
+    }
+}
+
+impl<V: SoundModule> SoundModule for Polyphony<V> {
+    fn set_sampling_parameters(&mut self, params: &SamplingParameters) {
+        for voice in self.voices.iter_mut() {
+            voice.generator.set_sampling_parameters(params);
+        }
+        self.level_decay = (-1.0 / (params.sample_rate() * Duration::from_seconds(LEVEL_DECAY))).exp();
+    }
+
+    fn reset(&mut self) {
+        self.events.borrow_mut().clear();
+        for voice in self.voices.iter_mut() {
+            voice.generator.reset();
+            voice.controls.gate.set(false);
+            voice.note = None;
+            voice.held = false;
+            voice.active = false;
+            voice.retrigger = false;
+            voice.level = 0.0;
+        }
+    }
+}
+
+impl<V> SignalGenerator for Polyphony<V> where
+    V: SignalGenerator<Output=f32>
+{
+    type Output = f32;
+
+    fn next(&mut self) -> f32 {
+        self.process_events();
+        let level_decay = self.level_decay;
+        let mut sum = 0.0;
+        for voice in self.voices.iter_mut().filter(|voice| voice.active) {
+            let output = voice.generator.next();
+            sum += output;
+            voice.level = output.abs().max(voice.level * level_decay);
+            if voice.retrigger {
+                voice.retrigger = false;
+                voice.controls.gate.set(true);
+            } else if !voice.held && voice.level < SILENCE_THRESHOLD {
+                voice.active = false;
+            }
+        }
+        sum
+    }
+}
+
+#[test]
+fn test_polyphony() {
+    use envelope::adsr;
+    use oscillator::sine;
+
+    let mut poly = Polyphony::new(2, |controls| {
+        let envelope = adsr(controls.gate(), Duration::from_seconds(0.01), Duration::from_seconds(0.01),
+                            0.5, Duration::from_seconds(0.05));
+        sine(controls.frequency()).mul(envelope).mul(controls.velocity())
+    });
+    poly.set_sampling_parameters(&SamplingParameters::audio_cd());
+    assert_eq!(poly.next(), 0.0);
+    assert_eq!(poly.active_voices(), 0);
+
+    let keyboard = poly.keyboard();
+    keyboard.note_on(60, 1.0);
+    keyboard.note_on(64, 1.0);
+    poly.next();
+    assert_eq!(poly.held_notes(), vec![60, 64]);
+    // the oldest note is stolen
+    poly.note_on(67, 1.0);
+    assert_eq!(poly.held_notes(), vec![67, 64]);
+    assert!((0..1000).map(|_| poly.next().abs()).fold(0.0, f32::max) > 0.5);
+
+    // released voices stop being computed once they are silent
+    poly.all_notes_off();
+    (0..44100).for_each(|_| { poly.next(); });
+    assert_eq!(poly.active_voices(), 0);
+
+    // a released voice is reused before stealing held ones, otherwise the lowest
+    let mut poly = poly.with_stealing(Stealing::Lowest);
+    poly.note_on(60, 1.0);
+    poly.note_on(55, 1.0);
+    poly.note_off(60);
+    poly.note_on(72, 1.0);
+    assert_eq!(poly.held_notes(), vec![72, 55]);
+    poly.note_on(74, 1.0);
+    assert_eq!(poly.held_notes(), vec![72, 74]);
+}
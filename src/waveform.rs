@@ -3,6 +3,14 @@ use std;
 pub trait Waveform {
     /// Return the amplitude at the given phase offset in the interval `[0-1)`
     fn phase_amplitude(&self, phase: f32) -> f32;
+
+    /// Return the amplitude at the given phase offset when the phase advances
+    /// by `phase_increment` per sample. Band-limited waveforms use this to
+    /// smooth out discontinuities that would otherwise cause aliasing.
+    #[inline(always)]
+    fn sampled_amplitude(&self, phase: f32, _phase_increment: f32) -> f32 {
+        self.phase_amplitude(phase)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -21,6 +29,19 @@ pub struct Rect(pub f32);
 #[derive(Debug, Clone)]
 pub struct Wavetable<T>(pub T);
 
+/// A saw wave with PolyBLEP anti-aliasing.
+#[derive(Debug, Clone)]
+pub struct BlSaw;
+
+/// A triangle wave with PolyBLAMP anti-aliasing.
+#[derive(Debug, Clone)]
+pub struct BlTriangle;
+
+/// A pulse wave with PolyBLEP anti-aliasing that is high for the given
+/// fraction of each period.
+#[derive(Debug, Clone)]
+pub struct BlPulse(pub f32);
+
 impl Waveform for Sine {
     #[inline(always)]
     fn phase_amplitude(&self, phase: f32) -> f32 {
@@ -73,3 +94,105 @@ impl<T> Waveform for Wavetable<T> where
         (1.0 - interp) * self.0[index1] + interp * self.0[index2]
     }
 }
+
+/// The polynomial band-limited step residual for a step of height 2 at phase
+/// zero, i.e. the difference between an integrated triangular pulse with a
+/// width of two samples and the naive step.
+#[inline(always)]
+fn poly_blep(phase: f32, phase_increment: f32) -> f32 {
+    if phase < phase_increment {
+        let t = phase / phase_increment;
+        t + t - t * t - 1.0
+    } else if phase > 1.0 - phase_increment {
+        let t = (phase - 1.0) / phase_increment;
+        t * t + t + t + 1.0
+    } else {
+        0.0
+    }
+}
+
+/// The polynomial band-limited ramp residual at phase zero, the integral of
+/// `poly_blep`. Needs to be scaled by the change of slope per sample.
+#[inline(always)]
+fn poly_blamp(phase: f32, phase_increment: f32) -> f32 {
+    if phase < phase_increment {
+        let t = 1.0 - phase / phase_increment;
+        t * t * t / 3.0
+    } else if phase > 1.0 - phase_increment {
+        let t = (phase - 1.0) / phase_increment + 1.0;
+        t * t * t / 3.0
+    } else {
+        0.0
+    }
+}
+
+/// Wrap a phase into the interval `[0-1)`.
+#[inline(always)]
+fn wrap_phase(phase: f32) -> f32 {
+    phase - phase.floor()
+}
+
+impl Waveform for BlSaw {
+    #[inline(always)]
+    fn phase_amplitude(&self, phase: f32) -> f32 {
+        Saw.phase_amplitude(phase)
+    }
+
+    #[inline(always)]
+    fn sampled_amplitude(&self, phase: f32, phase_increment: f32) -> f32 {
+        let dt = phase_increment.abs().min(0.5);
+        self.phase_amplitude(phase) - poly_blep(phase, dt)
+    }
+}
+
+impl Waveform for BlTriangle {
+    #[inline(always)]
+    fn phase_amplitude(&self, phase: f32) -> f32 {
+        Triangle.phase_amplitude(phase)
+    }
+
+    #[inline(always)]
+    fn sampled_amplitude(&self, phase: f32, phase_increment: f32) -> f32 {
+        let dt = phase_increment.abs().min(0.25);
+        // the slope changes by -8 at the top and by 8 at the bottom, which is
+        // split in half because the residual is normalized to a step of 2
+        self.phase_amplitude(phase)
+            - 4.0 * dt * poly_blamp(wrap_phase(phase - 0.25), dt)
+            + 4.0 * dt * poly_blamp(wrap_phase(phase - 0.75), dt)
+    }
+}
+
+impl Waveform for BlPulse {
+    #[inline(always)]
+    fn phase_amplitude(&self, phase: f32) -> f32 {
+        if phase < self.0 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+
+    #[inline(always)]
+    fn sampled_amplitude(&self, phase: f32, phase_increment: f32) -> f32 {
+        let dt = phase_increment.abs().min(0.5);
+        self.phase_amplitude(phase)
+            + poly_blep(phase, dt)
+            - poly_blep(wrap_phase(phase - self.0), dt)
+    }
+}
+
+#[test]
+fn test_band_limited_waveforms() {
+    let dt = 0.01;
+    for &phase in [0.1, 0.3, 0.45, 0.6, 0.9].iter() {
+        assert_eq!(BlSaw.sampled_amplitude(phase, dt), Saw.phase_amplitude(phase));
+        assert_eq!(BlPulse(0.25).sampled_amplitude(phase, dt), BlPulse(0.25).phase_amplitude(phase));
+        assert!((BlTriangle.sampled_amplitude(phase, dt) - Triangle.phase_amplitude(phase)).abs() < 1e-6);
+    }
+    // discontinuities are smoothed towards their midpoint
+    assert_eq!(BlSaw.sampled_amplitude(0.0, dt), 0.0);
+    assert_eq!(BlPulse(0.25).sampled_amplitude(0.25, dt), 0.0);
+    // corners are rounded off
+    assert!(BlTriangle.sampled_amplitude(0.25, dt) < 1.0);
+    assert!(BlTriangle.sampled_amplitude(0.75, dt) > -1.0);
+}